@@ -3,13 +3,61 @@
 
 #define I2C_ADDRESS 0x08
 
+// Framing shared with the Pico, see core/src/gadget_protocol.rs.
+// version | type | sequence | payload length | payload... | CRC-8
+#define PROTOCOL_VERSION 1
+#define HEADER_LEN 4
+#define MAX_FRAME_LEN 32
+#define TYPE_JOYSTICK_SAMPLE 0x01
+#define TYPE_VERSION 0x02
+#define TYPE_LCD_TEXT 0x10
+#define TYPE_LCD_CLEAR 0x11
+#define TYPE_LCD_CURSOR 0x12
+#define TYPE_PING 0x13
+#define TYPE_LCD_DEFINE_CHAR 0x14
+
 // initialize the library with the numbers of the interface pins
 LiquidCrystal lcd(7, 8, 9, 10, 11, 12);
-String lcdText = "";
 int joyX = 0;
 int joyY = 0;
 int joyPressed = 0;
 
+// Sequence number for the next frame we send.
+uint8_t sequence = 0;
+// The next read gets our version instead of a joystick sample.
+volatile bool pingPending = false;
+
+// Frames from the Pico are queued here and acted on in loop(), the LCD
+// is too slow to drive from the I2C interrupt.
+#define QUEUE_LEN 8
+uint8_t queue[QUEUE_LEN][MAX_FRAME_LEN];
+volatile uint8_t queueHead = 0;
+volatile uint8_t queueTail = 0;
+
+// CRC-8/SMBUS, polynomial 0x07.
+uint8_t crc8(const uint8_t *data, int len) {
+  uint8_t crc = 0;
+  for (int i = 0; i < len; i++) {
+    crc ^= data[i];
+    for (int bit = 0; bit < 8; bit++) {
+      crc = (crc & 0x80) ? (crc << 1) ^ 0x07 : (crc << 1);
+    }
+  }
+  return crc;
+}
+
+// Sends a frame with the given payload in reply to a read.
+void sendFrame(uint8_t type, const uint8_t *payload, uint8_t len) {
+  uint8_t frame[MAX_FRAME_LEN];
+  frame[0] = PROTOCOL_VERSION;
+  frame[1] = type;
+  frame[2] = sequence++;
+  frame[3] = len;
+  memcpy(frame + HEADER_LEN, payload, len);
+  frame[HEADER_LEN + len] = crc8(frame, HEADER_LEN + len);
+  Wire.write(frame, HEADER_LEN + len + 1);
+}
+
 void setup() {
   Wire.begin(I2C_ADDRESS);
   Wire.onReceive(receiveEvent);
@@ -23,16 +71,42 @@ void setup() {
   lcd.print("Hello world.");
 }
 
+// Acts on a frame from the Pico which has already been checked.
+void handleFrame(const uint8_t *frame) {
+  uint8_t len = frame[3];
+  const uint8_t *payload = frame + HEADER_LEN;
+  switch (frame[1]) {
+    case TYPE_LCD_TEXT:
+      for (int i = 0; i < len; i++) {
+        lcd.write(payload[i]);
+      }
+      break;
+    case TYPE_LCD_CLEAR:
+      lcd.clear();
+      break;
+    case TYPE_LCD_CURSOR:
+      if (len == 2) {
+        lcd.setCursor(payload[0], payload[1]);
+      }
+      break;
+    case TYPE_LCD_DEFINE_CHAR:
+      if (len == 9) {
+        lcd.createChar(payload[0], (uint8_t *)payload + 1);
+        // Writes go to character memory until the cursor is set.
+        lcd.setCursor(0, 0);
+      }
+      break;
+  }
+}
+
 void loop() {
   joyX = analogRead(A0);
   joyY = analogRead(A1);
   joyPressed = digitalRead(2);
 
-  if (lcdText.length() != 0) {
-    lcd.clear();
-    lcd.setCursor(0, 0);
-    lcd.print(lcdText);
-    lcdText = "";
+  while (queueTail != queueHead) {
+    handleFrame(queue[queueTail]);
+    queueTail = (queueTail + 1) % QUEUE_LEN;
   }
 
   delay(1);
@@ -40,19 +114,50 @@ void loop() {
 
 // Called when Pico sends data to Arduino
 void receiveEvent(int bytes) {
-  lcdText = "";
+  uint8_t frame[MAX_FRAME_LEN];
+  int len = 0;
   while (Wire.available()) {
-    lcdText += (char)Wire.read();
+    uint8_t b = Wire.read();
+    if (len < MAX_FRAME_LEN) {
+      frame[len++] = b;
+    }
   }
+
+  // Drop anything corrupt or from a different protocol version.
+  if (len < HEADER_LEN + 1 || frame[3] != len - HEADER_LEN - 1) {
+    return;
+  }
+  if (crc8(frame, len - 1) != frame[len - 1] || frame[0] != PROTOCOL_VERSION) {
+    return;
+  }
+
+  if (frame[1] == TYPE_PING) {
+    pingPending = true;
+    return;
+  }
+
+  uint8_t next = (queueHead + 1) % QUEUE_LEN;
+  if (next == queueTail) {
+    // Full, the LCD is behind.
+    return;
+  }
+  memcpy(queue[queueHead], frame, len);
+  queueHead = next;
 }
 
 void requestEvent() {
+  if (pingPending) {
+    pingPending = false;
+    uint8_t version = PROTOCOL_VERSION;
+    sendFrame(TYPE_VERSION, &version, 1);
+    return;
+  }
+
   uint8_t data[5];
   data[0] = (joyX & 0xff00) >> 8;
   data[1] = joyX & 0xff;
   data[2] = (joyY & 0xff00) >> 8;
   data[3] = joyY & 0xff;
   data[4] = (joyPressed == LOW) ? 1 : 0;
-  Wire.write(data, 5);
+  sendFrame(TYPE_JOYSTICK_SAMPLE, data, 5);
 }
-
@@ -0,0 +1,25 @@
+// Absolute positioning: where on the screen the joystick puts the pointer.
+//
+// Deflection maps straight to a screen position around a centre, so
+// letting go of the stick returns the pointer to the centre.
+
+use crate::calibration::CALIBRATED_RANGE;
+use crate::mouse::Point2D;
+
+/// Largest coordinate, the screen runs from 0 to this on each axis.
+pub const ABSOLUTE_MAX: u16 = 0x7fff;
+/// How far full deflection moves from the centre, half the screen so the
+/// edges can be reached from the middle.
+const ABSOLUTE_REACH: i32 = 0x4000;
+
+/// Screen position of the pointer for a deflection of the stick.
+pub fn absolute_position(centre: &Point2D<u16>, deflection: &Point2D<i16>) -> Point2D<u16> {
+    let axis = |c: u16, d: i16| {
+        let position = i32::from(c) + i32::from(d) * ABSOLUTE_REACH / CALIBRATED_RANGE;
+        u16::try_from(position.clamp(0, i32::from(ABSOLUTE_MAX))).unwrap()
+    };
+    Point2D {
+        x: axis(centre.x, deflection.x),
+        y: axis(centre.y, deflection.y),
+    }
+}
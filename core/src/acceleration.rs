@@ -0,0 +1,245 @@
+// Pointer acceleration: turns joystick deflection into cursor speed.
+//
+// Everything is integer fixed point, the RP2040 has no FPU.  Deflection and
+// curve outputs are in 1/1024ths of full scale.
+
+use crate::absolute::ABSOLUTE_MAX;
+use crate::calibration::JoystickCalibration;
+use crate::deadzone::Deadzone;
+use crate::filter::FilterKind;
+use crate::key_table::MouseButton;
+use crate::mouse::Point2D;
+use crate::orientation::Orientation;
+
+/// Fixed point one, for deflection and curve outputs.
+pub const UNIT: i32 = 1024;
+
+/// Joystick counts from centre treated as full deflection.  The Arduino
+/// reads 10 bits, so this is a little under half the range.
+const FULL_DEFLECTION: i32 = 480;
+
+/// Points of the lookup table curve, (deflection, speed).  Gentle near the
+/// centre for precise work, steep at the edges.
+const TABLE_CURVE: [(i32, i32); 6] = [
+    (0, 0),
+    (128, 16),
+    (384, 128),
+    (640, 352),
+    (896, 736),
+    (1024, 1024),
+];
+
+/// Smoothed deflection below which adaptive mode doesn't accelerate.
+const ADAPTIVE_THRESHOLD: i32 = 256;
+/// Extra gain per unit of smoothed deflection above the threshold, in 1/1024ths.
+const ADAPTIVE_INCLINE: i32 = 2048;
+/// Cap on the adaptive gain, in 1/1024ths.
+const ADAPTIVE_MAX_GAIN: i32 = 3 * UNIT;
+/// How quickly the adaptive mode's smoothed deflection follows the stick, 1/N per update.
+const ADAPTIVE_SMOOTHING: i32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Curve {
+    /// Speed proportional to deflection.
+    #[default]
+    Linear,
+    /// Speed proportional to deflection raised to a whole power.
+    Power(u8),
+    /// Piecewise linear through `TABLE_CURVE`.
+    Table,
+    /// Linear, with extra gain which builds up while the stick is held far
+    /// from centre, similar to libinput's adaptive profile.  Quick flicks
+    /// stay precise, sustained pushes cross the screen quickly.
+    Adaptive,
+}
+
+impl Curve {
+    /// The next curve, for cycling through them from a key.
+    pub fn next(self) -> Self {
+        match self {
+            Curve::Linear => Curve::Power(2),
+            Curve::Power(2) => Curve::Power(3),
+            Curve::Power(_) => Curve::Table,
+            Curve::Table => Curve::Adaptive,
+            Curve::Adaptive => Curve::Linear,
+        }
+    }
+}
+
+/// How joystick deflection maps to speed.
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct SpeedProfile {
+    pub curve: Curve,
+    /// Percentage applied to deflection before the curve.
+    pub sensitivity: u16,
+    /// Speed at full deflection, in units per second.
+    pub max_speed: u16,
+}
+
+/// Scrolling wants fine control near the centre and a modest top speed,
+/// in wheel detents per second.
+pub const SCROLL_SPEED: SpeedProfile = SpeedProfile {
+    curve: Curve::Power(2),
+    sensitivity: 100,
+    max_speed: 40,
+};
+
+/// User adjustable pointer settings.
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct PointerSettings {
+    pub curve: Curve,
+    pub deadzone: Deadzone,
+    /// Smoothing applied to joystick readings.
+    pub filter: FilterKind,
+    /// How the joystick is mounted.
+    pub orientation: Orientation,
+    /// None until the joystick has been calibrated, raw readings are used.
+    pub calibration: Option<JoystickCalibration>,
+    /// Percentage applied to deflection before the curve.  Higher reaches
+    /// the maximum speed with less deflection.
+    pub sensitivity: u16,
+    /// Cursor speed at full deflection, in pixels per second.
+    pub max_speed: u16,
+    /// Precision mode divides the cursor speed by this.
+    pub precision_divisor: u8,
+    /// What the joystick's push button clicks.
+    pub joystick_button: MouseButton,
+    /// Where the pointer rests in absolute mode, see `absolute_position`.
+    pub absolute_centre: Point2D<u16>,
+}
+
+pub const SENSITIVITY_STEP: u16 = 10;
+pub const SENSITIVITY_RANGE: (u16, u16) = (20, 400);
+pub const MAX_SPEED_STEP: u16 = 80;
+pub const MAX_SPEED_RANGE: (u16, u16) = (80, 4000);
+/// Divisors precision mode cycles through.
+pub const PRECISION_DIVISORS: [u8; 4] = [2, 4, 8, 16];
+
+impl Default for PointerSettings {
+    fn default() -> Self {
+        // Linear like the original hard-coded mapping, which reached about
+        // 600 pixels per second at full deflection but kept speeding up past
+        // it.  This one stops at max_speed.
+        Self {
+            curve: Curve::Linear,
+            deadzone: Deadzone::default(),
+            filter: FilterKind::None,
+            orientation: Orientation::default(),
+            calibration: None,
+            sensitivity: 100,
+            max_speed: 640,
+            precision_divisor: 4,
+            joystick_button: MouseButton::Left,
+            absolute_centre: Point2D { x: ABSOLUTE_MAX / 2, y: ABSOLUTE_MAX / 2 },
+        }
+    }
+}
+
+impl PointerSettings {
+    pub fn adjust_sensitivity(&mut self, up: bool) {
+        self.sensitivity = step(self.sensitivity, SENSITIVITY_STEP, SENSITIVITY_RANGE, up);
+    }
+
+    pub fn adjust_max_speed(&mut self, up: bool) {
+        self.max_speed = step(self.max_speed, MAX_SPEED_STEP, MAX_SPEED_RANGE, up);
+    }
+
+    pub fn cycle_precision_divisor(&mut self) {
+        let next = PRECISION_DIVISORS.iter().position(|d| *d == self.precision_divisor).map_or(0, |i| i + 1);
+        self.precision_divisor = PRECISION_DIVISORS[next % PRECISION_DIVISORS.len()];
+    }
+
+    /// Speed of the cursor, in pixels per second.  `precise` slows it down
+    /// by `precision_divisor`.
+    pub fn pointer_speed(&self, precise: bool) -> SpeedProfile {
+        let divisor = if precise { u16::from(self.precision_divisor).max(1) } else { 1 };
+        SpeedProfile {
+            curve: self.curve,
+            sensitivity: self.sensitivity,
+            max_speed: self.max_speed / divisor,
+        }
+    }
+}
+
+fn step(value: u16, step: u16, range: (u16, u16), up: bool) -> u16 {
+    let value = if up { value.saturating_add(step) } else { value.saturating_sub(step) };
+    value.clamp(range.0, range.1)
+}
+
+/// Integer square root, rounding down.
+pub fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method from an over-estimate converges downwards.
+    let mut x = 1u64 << (64 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+fn table_lookup(table: &[(i32, i32)], x: i32) -> i32 {
+    for pair in table.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if x <= x1 {
+            return y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+        }
+    }
+    table.last().map_or(0, |(_, y)| *y)
+}
+
+/// Applies a `Curve`, keeping the state adaptive mode needs between updates.
+#[derive(Default)]
+pub struct Accelerator {
+    smoothed_deflection: i32,
+}
+
+impl Accelerator {
+    /// Converts joystick counts from centre into a velocity, in
+    /// `subpixels` per update when there are `updates_per_second` updates.
+    pub fn velocity(
+        &mut self,
+        settings: &SpeedProfile,
+        dx: i32,
+        dy: i32,
+        subpixels: i64,
+        updates_per_second: i64,
+    ) -> (i64, i64) {
+        // Apply the curve to the distance from centre rather than per axis,
+        // so diagonals move at the same speed as straight lines.
+        let counts = isqrt((i64::from(dx).pow(2) + i64::from(dy).pow(2)) as u64) as i32;
+        let deflection = (counts * UNIT / FULL_DEFLECTION * i32::from(settings.sensitivity) / 100).min(UNIT);
+
+        self.smoothed_deflection += (deflection - self.smoothed_deflection) / ADAPTIVE_SMOOTHING;
+
+        let speed = match settings.curve {
+            Curve::Linear => deflection,
+            Curve::Power(exponent) => {
+                (1..exponent).fold(deflection, |acc, _| acc * deflection / UNIT)
+            }
+            Curve::Table => table_lookup(&TABLE_CURVE, deflection),
+            Curve::Adaptive => {
+                let excess = (self.smoothed_deflection - ADAPTIVE_THRESHOLD).max(0);
+                let gain = (UNIT + excess * ADAPTIVE_INCLINE / UNIT).min(ADAPTIVE_MAX_GAIN);
+                // Divide by the maximum gain so full deflection is still max_speed.
+                deflection * gain / ADAPTIVE_MAX_GAIN
+            }
+        };
+
+        if counts == 0 {
+            return (0, 0);
+        }
+
+        // speed is a fraction of max_speed, split it between the axes.
+        let per_update = i64::from(speed) * i64::from(settings.max_speed) * subpixels
+            / (i64::from(UNIT) * updates_per_second);
+        (
+            per_update * i64::from(dx) / i64::from(counts),
+            per_update * i64::from(dy) / i64::from(counts),
+        )
+    }
+}
@@ -0,0 +1,147 @@
+// Auto mouse mode: after the joystick has been moved, the `Dual` keys act
+// as mouse buttons instead of keyboard keys.  Typing, or leaving the mouse
+// alone for a while, returns to keyboard mode.
+
+use usbd_human_interface_device::page::Keyboard;
+
+use crate::key_table::MOUSE_MODIFIER_KEYS;
+
+/// User adjustable auto mouse settings.
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct AutoMouseConfig {
+    /// Pixels the pointer must move before mouse mode starts.
+    pub activation_distance: u16,
+    /// Return to keyboard mode after this long without mouse movement or
+    /// buttons, None to stay in mouse mode until a key is typed.
+    pub timeout_ms: Option<u32>,
+}
+
+pub const ACTIVATION_DISTANCE_STEP: u16 = 5;
+pub const ACTIVATION_DISTANCE_RANGE: (u16, u16) = (1, 100);
+pub const TIMEOUT_STEP_MS: u32 = 1000;
+/// Past the longest timeout comes none at all.
+pub const TIMEOUT_RANGE_MS: (u32, u32) = (1000, 30000);
+
+impl Default for AutoMouseConfig {
+    fn default() -> Self {
+        Self {
+            activation_distance: 5,
+            timeout_ms: Some(5000),
+        }
+    }
+}
+
+impl AutoMouseConfig {
+    pub fn adjust_activation_distance(&mut self, up: bool) {
+        let distance = if up {
+            self.activation_distance.saturating_add(ACTIVATION_DISTANCE_STEP)
+        } else {
+            self.activation_distance.saturating_sub(ACTIVATION_DISTANCE_STEP)
+        };
+        self.activation_distance = distance.clamp(ACTIVATION_DISTANCE_RANGE.0, ACTIVATION_DISTANCE_RANGE.1);
+    }
+
+    /// Lengthens or shortens the timeout, going from the longest to none.
+    pub fn adjust_timeout(&mut self, up: bool) {
+        let (min, max) = TIMEOUT_RANGE_MS;
+        self.timeout_ms = match (self.timeout_ms, up) {
+            (None, true) => None,
+            (None, false) => Some(max),
+            (Some(t), true) if t >= max => None,
+            (Some(t), true) => Some((t + TIMEOUT_STEP_MS).min(max)),
+            (Some(t), false) => Some(t.saturating_sub(TIMEOUT_STEP_MS).max(min)),
+        };
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum AutoMouseEvent {
+    Entered,
+    Exited,
+}
+
+pub struct AutoMouse {
+    /// Keys which can be held without leaving mouse mode, the ones used as
+    /// modifiers along with a click.
+    keep_alive_keys: &'static [Keyboard],
+    // Distance moved without a key being typed, stops at the activation distance.
+    distance: u64,
+    // When the mouse last moved or had a button held.
+    last_activity_ms: u64,
+}
+
+impl Default for AutoMouse {
+    fn default() -> Self {
+        Self { keep_alive_keys: &MOUSE_MODIFIER_KEYS, distance: 0, last_activity_ms: 0 }
+    }
+}
+
+impl AutoMouse {
+    pub fn is_active(&self, config: &AutoMouseConfig) -> bool {
+        self.distance >= u64::from(config.activation_distance)
+    }
+
+    /// Called once per mouse report.  `distance` is how far the report
+    /// moves, `keys` and `media_held` are what's held on the keyboard.
+    pub fn update(
+        &mut self,
+        now_ms: u64,
+        distance: u64,
+        buttons_held: bool,
+        keys: &[Keyboard],
+        media_held: bool,
+        config: &AutoMouseConfig,
+    ) -> Option<AutoMouseEvent> {
+        let was_active = self.is_active(config);
+
+        let typing = media_held || keys.iter().any(|k| !self.keep_alive_keys.contains(k));
+        if typing {
+            self.distance = 0;
+        } else if distance != 0 || buttons_held {
+            self.last_activity_ms = now_ms;
+            self.distance = (self.distance + distance).min(config.activation_distance.into());
+        } else if config.timeout_ms.is_some_and(|t| now_ms - self.last_activity_ms >= u64::from(t)) {
+            self.distance = 0;
+        }
+
+        match (was_active, self.is_active(config)) {
+            (false, true) => Some(AutoMouseEvent::Entered),
+            (true, false) => Some(AutoMouseEvent::Exited),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_steps_through_none() {
+        let mut config = AutoMouseConfig { timeout_ms: Some(29500), ..Default::default() };
+        config.adjust_timeout(true);
+        assert_eq!(config.timeout_ms, Some(30000));
+        config.adjust_timeout(true);
+        assert_eq!(config.timeout_ms, None);
+        config.adjust_timeout(true);
+        assert_eq!(config.timeout_ms, None);
+        config.adjust_timeout(false);
+        assert_eq!(config.timeout_ms, Some(30000));
+        (0..40).for_each(|_| config.adjust_timeout(false));
+        assert_eq!(config.timeout_ms, Some(1000));
+    }
+
+    #[test]
+    fn follows_config_changes() {
+        let mut config = AutoMouseConfig::default();
+        let mut auto_mouse = AutoMouse::default();
+        assert_eq!(auto_mouse.update(0, 3, false, &[], false, &config), None);
+        config.adjust_activation_distance(false);
+        assert!(auto_mouse.is_active(&config));
+
+        // Still there after the default timeout, now it's longer.
+        config.adjust_timeout(true);
+        assert_eq!(auto_mouse.update(5000, 0, false, &[], false, &config), None);
+        assert_eq!(auto_mouse.update(6000, 0, false, &[], false, &config), Some(AutoMouseEvent::Exited));
+    }
+}
@@ -0,0 +1,204 @@
+// Full range joystick calibration.
+//
+// The stick's travel isn't symmetrical about its resting position, so
+// readings are scaled separately either side of the centre to give the
+// same range in every direction.
+
+use crate::mouse::Point2D;
+use crate::mouse::MOUSE_REPORT_PERIOD_MS;
+
+/// Calibrated readings run from minus this to plus this.
+pub const CALIBRATED_RANGE: i32 = 512;
+
+/// Each direction must be pushed at least this far from the start for the
+/// calibration to be accepted.
+const MIN_SPAN: i16 = 200;
+/// Readings must stay this close together to count as released.
+const REST_JITTER: i16 = 3;
+/// How long the stick must rest before calibration finishes.
+const REST_UPDATES: u32 = 1000 / MOUSE_REPORT_PERIOD_MS;
+/// Give up if calibration isn't finished in this time.
+const TIMEOUT_UPDATES: u32 = 30_000 / MOUSE_REPORT_PERIOD_MS;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct AxisCalibration {
+    pub min: i16,
+    pub centre: i16,
+    pub max: i16,
+}
+
+impl AxisCalibration {
+    fn normalize(&self, raw: i16) -> i16 {
+        let offset = i32::from(raw) - i32::from(self.centre);
+        let span = if offset < 0 {
+            i32::from(self.centre) - i32::from(self.min)
+        } else {
+            i32::from(self.max) - i32::from(self.centre)
+        };
+        if span <= 0 {
+            return 0;
+        }
+        let scaled = (offset * CALIBRATED_RANGE / span).clamp(-CALIBRATED_RANGE, CALIBRATED_RANGE);
+        i16::try_from(scaled).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct JoystickCalibration {
+    pub x: AxisCalibration,
+    pub y: AxisCalibration,
+}
+
+impl JoystickCalibration {
+    /// Scales a raw reading so each direction covers `CALIBRATED_RANGE`.
+    pub fn normalize(&self, raw: &Point2D<i16>) -> Point2D<i16> {
+        Point2D {
+            x: self.x.normalize(raw.x),
+            y: self.y.normalize(raw.y),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum CalibrationError {
+    /// The stick wasn't pushed to its extremes and released in time.
+    Timeout,
+}
+
+/// Guided calibration.  Start with the stick at rest, push it to its limit
+/// in every direction, then let go.
+#[derive(Default)]
+pub struct Calibrator {
+    start: Option<Point2D<i16>>,
+    min: Point2D<i16>,
+    max: Point2D<i16>,
+    rest_start: Point2D<i16>,
+    rest_sum: Point2D<i32>,
+    rest_count: u32,
+    updates: u32,
+}
+
+impl Calibrator {
+    /// Feed a raw reading, returns the result once calibration is over.
+    pub fn update(&mut self, raw: &Point2D<i16>) -> Option<Result<JoystickCalibration, CalibrationError>> {
+        self.updates += 1;
+        if self.updates > TIMEOUT_UPDATES {
+            return Some(Err(CalibrationError::Timeout));
+        }
+
+        let start = *self.start.get_or_insert(*raw);
+        if self.updates == 1 {
+            self.min = *raw;
+            self.max = *raw;
+        }
+        self.min.x = self.min.x.min(raw.x);
+        self.min.y = self.min.y.min(raw.y);
+        self.max.x = self.max.x.max(raw.x);
+        self.max.y = self.max.y.max(raw.y);
+
+        if (raw.x - self.rest_start.x).abs() > REST_JITTER || (raw.y - self.rest_start.y).abs() > REST_JITTER {
+            self.rest_start = *raw;
+            self.rest_sum = Point2D::default();
+            self.rest_count = 0;
+        }
+        self.rest_sum.x += i32::from(raw.x);
+        self.rest_sum.y += i32::from(raw.y);
+        self.rest_count += 1;
+
+        let explored = start.x - self.min.x >= MIN_SPAN && self.max.x - start.x >= MIN_SPAN
+            && start.y - self.min.y >= MIN_SPAN && self.max.y - start.y >= MIN_SPAN;
+        let returned = (self.rest_start.x - start.x).abs() < MIN_SPAN / 2
+            && (self.rest_start.y - start.y).abs() < MIN_SPAN / 2;
+        if !explored || !returned || self.rest_count < REST_UPDATES {
+            return None;
+        }
+
+        let count = i32::try_from(self.rest_count).unwrap();
+        let centre = Point2D {
+            x: i16::try_from(self.rest_sum.x / count).unwrap(),
+            y: i16::try_from(self.rest_sum.y / count).unwrap(),
+        };
+        Some(Ok(JoystickCalibration {
+            x: AxisCalibration { min: self.min.x, centre: centre.x, max: self.max.x },
+            y: AxisCalibration { min: self.min.y, centre: centre.y, max: self.max.y },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X: AxisCalibration = AxisCalibration { min: -300, centre: 100, max: 900 };
+
+    #[test]
+    fn uneven_travel_gives_even_range() {
+        assert_eq!(X.normalize(100), 0);
+        assert_eq!(X.normalize(-300), -512);
+        assert_eq!(X.normalize(900), 512);
+        // Halfway each side is half the range, though the raw distances differ.
+        assert_eq!(X.normalize(-100), -256);
+        assert_eq!(X.normalize(500), 256);
+    }
+
+    #[test]
+    fn beyond_the_extremes_is_clamped() {
+        assert_eq!(X.normalize(-1000), -512);
+        assert_eq!(X.normalize(i16::MIN), -512);
+        assert_eq!(X.normalize(2000), 512);
+        assert_eq!(X.normalize(i16::MAX), 512);
+    }
+
+    #[test]
+    fn side_with_no_travel_reads_centre() {
+        let axis = AxisCalibration { min: 100, centre: 100, max: 600 };
+        assert_eq!(axis.normalize(50), 0);
+        assert_eq!(axis.normalize(100), 0);
+        assert_eq!(axis.normalize(350), 256);
+    }
+
+    /// Feeds readings until the calibrator gives a result.
+    fn feed(calibrator: &mut Calibrator, readings: impl IntoIterator<Item = Point2D<i16>>) -> Option<Result<JoystickCalibration, CalibrationError>> {
+        readings.into_iter().find_map(|raw| calibrator.update(&raw))
+    }
+
+    /// Holds the stick near `raw`, wobbling by one either way.
+    fn held(raw: Point2D<i16>, updates: u32) -> impl Iterator<Item = Point2D<i16>> {
+        (0..updates).map(move |i| Point2D { x: raw.x + (i % 3) as i16 - 1, y: raw.y })
+    }
+
+    #[test]
+    fn guided_calibration() {
+        let mut calibrator = Calibrator::default();
+        let start = Point2D { x: 2000, y: 2100 };
+        assert_eq!(feed(&mut calibrator, held(start, REST_UPDATES * 2)), None);
+
+        // Push to each edge in turn, then part way into a corner.
+        let extremes = [
+            Point2D { x: 1500, y: 2100 },
+            Point2D { x: 2700, y: 2100 },
+            Point2D { x: 2000, y: 1700 },
+            Point2D { x: 2000, y: 2450 },
+            Point2D { x: 1600, y: 1800 },
+        ];
+        for extreme in extremes {
+            assert_eq!(feed(&mut calibrator, held(extreme, 20)), None);
+        }
+
+        // Let go, and it settles a little off where it started.
+        let rest = Point2D { x: 2010, y: 2095 };
+        assert_eq!(feed(&mut calibrator, held(rest, REST_UPDATES - 1)), None);
+        let result = feed(&mut calibrator, held(rest, 1)).unwrap().unwrap();
+        assert_eq!(result.x, AxisCalibration { min: 1499, centre: 2009, max: 2701 });
+        assert_eq!(result.y, AxisCalibration { min: 1700, centre: 2095, max: 2450 });
+        assert_eq!(result.normalize(&rest), Point2D { x: 0, y: 0 });
+        assert_eq!(result.normalize(&Point2D { x: 1499, y: 2450 }), Point2D { x: -512, y: 512 });
+    }
+
+    #[test]
+    fn calibration_times_out_without_reaching_the_extremes() {
+        let mut calibrator = Calibrator::default();
+        let result = feed(&mut calibrator, held(Point2D { x: 2000, y: 2000 }, TIMEOUT_UPDATES + 1));
+        assert_eq!(result, Some(Err(CalibrationError::Timeout)));
+    }
+}
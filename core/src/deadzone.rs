@@ -0,0 +1,166 @@
+// Handling of the joystick near its centre: a deadzone so the resting stick
+// doesn't move the cursor, and slow recalibration of the centre as the
+// stick drifts with temperature.
+
+use crate::acceleration::isqrt;
+use crate::mouse::Point2D;
+use crate::mouse::MOUSE_REPORT_PERIOD_MS;
+
+/// Readings must stay this close to where the stationary period started.
+const STATIONARY_JITTER: i16 = 3;
+/// How long the stick must be still before the origin is moved.
+const RECENTRE_AFTER_MS: u32 = 2000;
+const RECENTRE_AFTER_UPDATES: u32 = RECENTRE_AFTER_MS / MOUSE_REPORT_PERIOD_MS;
+
+pub const RADIUS_STEP: u16 = 4;
+pub const RADIUS_RANGE: (u16, u16) = (0, 64);
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum DeadzoneShape {
+    /// A circle around the centre, diagonals behave like straight lines.
+    #[default]
+    Radial,
+    /// A cross through the centre, which makes pure horizontal or vertical
+    /// movement easy.
+    Axial,
+}
+
+impl DeadzoneShape {
+    /// The other shape, for switching from a key.
+    pub fn next(self) -> Self {
+        match self {
+            DeadzoneShape::Radial => DeadzoneShape::Axial,
+            DeadzoneShape::Axial => DeadzoneShape::Radial,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Deadzone {
+    pub shape: DeadzoneShape,
+    /// Joystick counts from the centre which are ignored.
+    pub radius: u16,
+}
+
+impl Default for Deadzone {
+    fn default() -> Self {
+        Self {
+            shape: DeadzoneShape::Radial,
+            radius: 12,
+        }
+    }
+}
+
+impl Deadzone {
+    pub fn adjust_radius(&mut self, up: bool) {
+        let radius = if up { self.radius.saturating_add(RADIUS_STEP) } else { self.radius.saturating_sub(RADIUS_STEP) };
+        self.radius = radius.clamp(RADIUS_RANGE.0, RADIUS_RANGE.1);
+    }
+
+    /// Removes the deadzone from an offset from centre.  Movement starts
+    /// from zero at the edge of the deadzone rather than jumping.
+    pub fn apply(&self, dx: i32, dy: i32) -> (i32, i32) {
+        let radius = i32::from(self.radius);
+        match self.shape {
+            DeadzoneShape::Radial => {
+                let r = isqrt((i64::from(dx).pow(2) + i64::from(dy).pow(2)) as u64) as i32;
+                if r <= radius {
+                    (0, 0)
+                } else {
+                    (dx * (r - radius) / r, dy * (r - radius) / r)
+                }
+            }
+            DeadzoneShape::Axial => {
+                let axis = |d: i32| d.signum() * (d.abs() - radius).max(0);
+                (axis(dx), axis(dy))
+            }
+        }
+    }
+}
+
+/// Watches for the stick resting inside the deadzone, and moves the origin
+/// to the average resting position.  A stick held steadily outside it is
+/// being used, not drifting.
+#[derive(Default)]
+pub struct DriftCompensator {
+    start: Point2D<i16>,
+    sum: Point2D<i32>,
+    count: u32,
+}
+
+impl DriftCompensator {
+    /// Returns a new origin when the stick has been resting long enough.
+    pub fn update(&mut self, raw: &Point2D<i16>, origin: &Point2D<i16>, deadzone: &Deadzone) -> Option<Point2D<i16>> {
+        let near_centre = deadzone.apply(i32::from(raw.x - origin.x), i32::from(raw.y - origin.y)) == (0, 0);
+        let still = (raw.x - self.start.x).abs() <= STATIONARY_JITTER && (raw.y - self.start.y).abs() <= STATIONARY_JITTER;
+
+        if !near_centre || !still {
+            self.restart(raw);
+            return None;
+        }
+
+        self.sum.x += i32::from(raw.x);
+        self.sum.y += i32::from(raw.y);
+        self.count += 1;
+
+        if self.count < RECENTRE_AFTER_UPDATES {
+            return None;
+        }
+
+        let count = i32::try_from(self.count).unwrap();
+        let average = Point2D {
+            x: i16::try_from(self.sum.x / count).unwrap(),
+            y: i16::try_from(self.sum.y / count).unwrap(),
+        };
+        self.restart(raw);
+        Some(average)
+    }
+
+    pub fn restart(&mut self, raw: &Point2D<i16>) {
+        self.start = *raw;
+        self.sum = Point2D::default();
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Holds the stick still at `raw` for long enough to recentre, returning
+    /// the new origin if there was one.
+    fn hold(raw: Point2D<i16>, deadzone: &Deadzone) -> Option<Point2D<i16>> {
+        let origin = Point2D { x: 0, y: 0 };
+        let mut drift = DriftCompensator::default();
+        drift.restart(&raw);
+        (0..RECENTRE_AFTER_UPDATES).find_map(|_| drift.update(&raw, &origin, deadzone))
+    }
+
+    #[test]
+    fn resting_inside_deadzone_recentres() {
+        let deadzone = Deadzone::default();
+        let raw = Point2D { x: 8, y: -8 };
+        assert_eq!(hold(raw, &deadzone), Some(raw));
+    }
+
+    #[test]
+    fn held_outside_deadzone_does_not_recentre() {
+        let deadzone = Deadzone::default();
+        // Just outside the radial deadzone, though inside the square around it.
+        assert_eq!(hold(Point2D { x: 10, y: 10 }, &deadzone), None);
+        assert_eq!(hold(Point2D { x: 20, y: 0 }, &deadzone), None);
+
+        let axial = Deadzone { shape: DeadzoneShape::Axial, ..deadzone };
+        assert_eq!(hold(Point2D { x: 10, y: 10 }, &axial), Some(Point2D { x: 10, y: 10 }));
+        assert_eq!(hold(Point2D { x: 0, y: 13 }, &axial), None);
+    }
+
+    #[test]
+    fn radius_stays_in_range() {
+        let mut deadzone = Deadzone::default();
+        (0..100).for_each(|_| deadzone.adjust_radius(true));
+        assert_eq!(deadzone.radius, RADIUS_RANGE.1);
+        (0..100).for_each(|_| deadzone.adjust_radius(false));
+        assert_eq!(deadzone.radius, RADIUS_RANGE.0);
+    }
+}
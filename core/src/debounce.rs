@@ -2,8 +2,9 @@
 // approx 10ms given the scan period of 1ms
 const COOLDOWN_TICKS: u64 = 50; 
 
-// Derives copy for easy array initialization.
-pub(crate) struct DebounceState {
+// Derives Default for easy array initialization.
+#[derive(Default)]
+pub struct DebounceState {
     state: bool,
     earliest_next_change_clock: u64
 }
@@ -21,12 +22,3 @@ impl DebounceState {
         self.state
     }
 }
-
-impl Default for DebounceState {
-    fn default() -> Self {
-        Self {
-            state: false,
-            earliest_next_change_clock: 0
-        }
-    }
-}
@@ -0,0 +1,216 @@
+// Smoothing of joystick readings, which jitter by a few counts even when
+// the stick is still.
+//
+// Integer fixed point like the rest of the pointer code, filtered values
+// are kept in 1/256ths of a count.
+
+use crate::acceleration::UNIT;
+use crate::mouse::Point2D;
+use crate::mouse::UPDATES_PER_SECOND;
+use crate::mouse::MOUSE_REPORT_PERIOD_MS;
+
+/// Fractions of a count filtered values are kept in.
+const SCALE: i64 = 256;
+/// Weight of each new reading for the moving average, in 1/1024ths.
+const EMA_ALPHA: i64 = 384;
+/// Readings the median is taken over, odd so there's a middle one.
+const MEDIAN_LEN: usize = 5;
+/// 1€ filter cutoff when the stick is still, in millihertz.  Lower
+/// removes more jitter.
+const ONE_EURO_MIN_CUTOFF_MHZ: i64 = 1000;
+/// How much the 1€ cutoff rises with speed, in millihertz per count per
+/// second.  Higher reduces lag when the stick moves quickly.
+const ONE_EURO_BETA: i64 = 7;
+/// Cutoff for smoothing the 1€ filter's speed estimate, in millihertz.
+const ONE_EURO_DERIVATIVE_CUTOFF_MHZ: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum FilterKind {
+    /// Readings are used as they are.
+    #[default]
+    None,
+    /// Exponential moving average, simple but lags a little.  Within a
+    /// count of a step after 150 ms.
+    Ema,
+    /// Median of the last few readings, removes spikes.  Within a count of
+    /// a step after 30 ms.
+    Median,
+    /// The 1€ filter, smooths heavily when still and lightly when moving,
+    /// see Casiez et al., CHI 2012.  Within a count of a step after 150 ms,
+    /// sooner the bigger the step.
+    OneEuro,
+}
+
+impl FilterKind {
+    /// The next filter, for cycling through them from a key.
+    pub fn next(self) -> Self {
+        match self {
+            FilterKind::None => FilterKind::Ema,
+            FilterKind::Ema => FilterKind::Median,
+            FilterKind::Median => FilterKind::OneEuro,
+            FilterKind::OneEuro => FilterKind::None,
+        }
+    }
+}
+
+/// Weight of a new reading for a first order low pass filter with the given
+/// cutoff, in 1/1024ths.  alpha = r / (1 + r) where r = 2π × cutoff × period.
+fn smoothing_factor(cutoff_mhz: i64) -> i64 {
+    // r in billionths, 6283 is 2π in thousandths.
+    let r = 6283 * cutoff_mhz * i64::from(MOUSE_REPORT_PERIOD_MS);
+    i64::from(UNIT) * r / (1_000_000_000 + r)
+}
+
+#[derive(Default)]
+struct AxisFilter {
+    primed: bool,
+    // Filtered value, in 1/SCALE counts.
+    value: i64,
+    // Filtered rate of change for the 1€ filter, in 1/SCALE counts per second.
+    derivative: i64,
+    // Recent readings for the median, oldest overwritten first.
+    history: [i16; MEDIAN_LEN],
+    next: usize,
+}
+
+impl AxisFilter {
+    fn update(&mut self, kind: FilterKind, reading: i16) -> i16 {
+        let scaled = i64::from(reading) * SCALE;
+        if !self.primed {
+            self.primed = true;
+            self.value = scaled;
+            self.derivative = 0;
+            self.history = [reading; MEDIAN_LEN];
+            return reading;
+        }
+
+        match kind {
+            FilterKind::None => self.value = scaled,
+            FilterKind::Ema => self.value += (scaled - self.value) * EMA_ALPHA / i64::from(UNIT),
+            FilterKind::Median => {
+                self.history[self.next] = reading;
+                self.next = (self.next + 1) % MEDIAN_LEN;
+                let mut sorted = self.history;
+                sorted.sort_unstable();
+                self.value = i64::from(sorted[MEDIAN_LEN / 2]) * SCALE;
+            }
+            FilterKind::OneEuro => {
+                let derivative = (scaled - self.value) * UPDATES_PER_SECOND;
+                self.derivative += (derivative - self.derivative)
+                    * smoothing_factor(ONE_EURO_DERIVATIVE_CUTOFF_MHZ) / i64::from(UNIT);
+                let cutoff = ONE_EURO_MIN_CUTOFF_MHZ + ONE_EURO_BETA * self.derivative.abs() / SCALE;
+                self.value += (scaled - self.value) * smoothing_factor(cutoff) / i64::from(UNIT);
+            }
+        }
+
+        // Round to the nearest count.
+        i16::try_from((self.value + SCALE / 2).div_euclid(SCALE)).unwrap()
+    }
+}
+
+/// Filters joystick readings, keeping the history the filters need.
+#[derive(Default)]
+pub struct JitterFilter {
+    kind: FilterKind,
+    x: AxisFilter,
+    y: AxisFilter,
+}
+
+impl JitterFilter {
+    pub fn apply(&mut self, kind: FilterKind, reading: &Point2D<i16>) -> Point2D<i16> {
+        if kind != self.kind {
+            self.kind = kind;
+            self.reset();
+        }
+        Point2D {
+            x: self.x.update(kind, reading.x),
+            y: self.y.update(kind, reading.y),
+        }
+    }
+
+    /// Forget the history, the next reading is passed through unchanged.
+    pub fn reset(&mut self) {
+        self.x = AxisFilter::default();
+        self.y = AxisFilter::default();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Readings of a stick resting at `centre` jittering by up to `noise`
+    /// counts, from a fixed seed so the test is repeatable.
+    fn noisy(centre: i16, noise: i16, len: usize) -> Vec<i16> {
+        let mut state: u32 = 0x2545_f491;
+        (0..len)
+            .map(|_| {
+                // xorshift32
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                let span = u32::try_from(2 * noise + 1).unwrap();
+                centre + i16::try_from(state % span).unwrap() - noise
+            })
+            .collect()
+    }
+
+    fn variance(values: &[i16]) -> f64 {
+        let mean = values.iter().map(|v| f64::from(*v)).sum::<f64>() / values.len() as f64;
+        values.iter().map(|v| (f64::from(*v) - mean).powi(2)).sum::<f64>() / values.len() as f64
+    }
+
+    const FILTERS: [FilterKind; 3] = [FilterKind::Ema, FilterKind::Median, FilterKind::OneEuro];
+
+    /// The latencies documented on `FilterKind`, in milliseconds.
+    fn latency_ms(kind: FilterKind) -> u32 {
+        match kind {
+            FilterKind::None => 0,
+            FilterKind::Ema => 150,
+            FilterKind::Median => 30,
+            FilterKind::OneEuro => 150,
+        }
+    }
+
+    #[test]
+    fn filters_reduce_jitter() {
+        let input = noisy(500, 4, 2000);
+        // Past where the filters start up.
+        let input_variance = variance(&input[100..]);
+        for kind in FILTERS {
+            let mut filter = AxisFilter::default();
+            let output: Vec<i16> = input.iter().map(|r| filter.update(kind, *r)).collect();
+            let output_variance = variance(&output[100..]);
+            assert!(output_variance < input_variance / 2.0, "{kind:?}: {output_variance} vs {input_variance}");
+            let mean = output[100..].iter().map(|v| f64::from(*v)).sum::<f64>() / 1900.0;
+            assert!((mean - 500.0).abs() < 1.0, "{kind:?}: {mean}");
+        }
+        // The 1€ filter is meant to smooth the resting stick heavily.
+        let mut filter = AxisFilter::default();
+        let output: Vec<i16> = input.iter().map(|r| filter.update(FilterKind::OneEuro, *r)).collect();
+        assert!(variance(&output[100..]) < input_variance / 10.0);
+    }
+
+    #[test]
+    fn no_filter_passes_readings_through() {
+        let input = noisy(500, 4, 100);
+        let mut filter = AxisFilter::default();
+        assert!(input.iter().all(|r| filter.update(FilterKind::None, *r) == *r));
+    }
+
+    #[test]
+    fn steps_settle_within_latency() {
+        for kind in FILTERS {
+            let updates = latency_ms(kind) / MOUSE_REPORT_PERIOD_MS;
+            for step in [-480, -100, 10, 100, 480] {
+                let mut filter = AxisFilter::default();
+                filter.update(kind, 0);
+                let settled = (0..updates).map(|_| filter.update(kind, step)).last().unwrap();
+                assert!((settled - step).abs() <= 1, "{kind:?} step {step}: {settled}");
+                // And stays there.
+                assert!((0..50).all(|_| (filter.update(kind, step) - step).abs() <= 1));
+            }
+        }
+    }
+}
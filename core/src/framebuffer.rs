@@ -0,0 +1,173 @@
+// A monochrome framebuffer laid out as the SSD1306 wants it: pages of eight
+// rows, a byte per column with the top row in bit 0.
+//
+// Nothing here touches hardware, frames can be drawn and looked at on the
+// host with `write_pbm`.
+//
+// Pages are split into chunks, the pieces a frame is sent to the display
+// in, so no one I2C write takes long.
+
+use core::fmt;
+use core::ops::Range;
+
+pub const WIDTH: usize = 128;
+const MAX_PAGES: usize = 8;
+/// Columns in a chunk of a page.
+pub const CHUNK_WIDTH: usize = 16;
+const CHUNKS_PER_PAGE: usize = WIDTH / CHUNK_WIDTH;
+
+/// A set of chunks, a bit each, page by page from the top.  Big enough for
+/// the most pages a frame has.
+pub type Chunks = u64;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pages: [[u8; WIDTH]; MAX_PAGES],
+    height: usize,
+}
+
+impl Framebuffer {
+    /// `height` is a multiple of 8, up to 64.
+    pub fn new(height: usize) -> Self {
+        assert!(height.is_multiple_of(8) && height / 8 <= MAX_PAGES);
+        Self { pages: [[0; WIDTH]; MAX_PAGES], height }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pages(&self) -> usize {
+        self.height / 8
+    }
+
+    pub fn page(&self, page: usize) -> &[u8; WIDTH] {
+        &self.pages[page]
+    }
+
+    /// Every chunk of the frame.
+    pub fn all_chunks(&self) -> Chunks {
+        Chunks::MAX >> (Chunks::BITS as usize - self.pages() * CHUNKS_PER_PAGE)
+    }
+
+    /// Chunks which differ from `other`.
+    pub fn changed_chunks(&self, other: &Framebuffer) -> Chunks {
+        (0..self.pages() * CHUNKS_PER_PAGE)
+            .filter(|chunk| self.chunk(*chunk) != other.chunk(*chunk))
+            .fold(0, |changed, chunk| changed | 1 << chunk)
+    }
+
+    /// The page and columns a chunk covers.
+    pub fn chunk_location(chunk: usize) -> (usize, Range<usize>) {
+        let column = chunk % CHUNKS_PER_PAGE * CHUNK_WIDTH;
+        (chunk / CHUNKS_PER_PAGE, column..column + CHUNK_WIDTH)
+    }
+
+    pub fn chunk(&self, chunk: usize) -> &[u8] {
+        let (page, columns) = Self::chunk_location(chunk);
+        &self.pages[page][columns]
+    }
+
+    /// Makes a chunk the same as in `other`.
+    pub fn copy_chunk(&mut self, other: &Framebuffer, chunk: usize) {
+        let (page, columns) = Self::chunk_location(chunk);
+        self.pages[page][columns.clone()].copy_from_slice(&other.pages[page][columns]);
+    }
+
+    pub fn clear(&mut self) {
+        self.pages = [[0; WIDTH]; MAX_PAGES];
+    }
+
+    /// Lights a pixel.  Anything off the screen is left out, so shapes can
+    /// hang over the edges.
+    pub fn set(&mut self, x: i32, y: i32) {
+        if let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y))
+            && x < WIDTH
+            && y < self.height
+        {
+            self.pages[y / 8][x] |= 1 << (y % 8);
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pages[y / 8][x] & 1 << (y % 8) != 0
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        for dy in 0..height {
+            for dx in 0..width {
+                self.set(x + dx, y + dy);
+            }
+        }
+    }
+
+    pub fn rect(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.fill_rect(x, y, width, 1);
+        self.fill_rect(x, y + height - 1, width, 1);
+        self.fill_rect(x, y, 1, height);
+        self.fill_rect(x + width - 1, y, 1, height);
+    }
+
+    /// Draws a bitmap given as rows, top first, with the leftmost of
+    /// `width` pixels in the highest bit.
+    pub fn bitmap(&mut self, x: i32, y: i32, rows: &[u8], width: i32) {
+        for (dy, row) in (0..).zip(rows) {
+            for dx in 0..width {
+                if row >> (width - 1 - dx) & 1 != 0 {
+                    self.set(x + dx, y + dy);
+                }
+            }
+        }
+    }
+
+    /// Writes the frame as a plain PBM image, lit pixels black.
+    pub fn write_pbm(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(out, "P1")?;
+        writeln!(out, "{} {}", WIDTH, self.height)?;
+        for y in 0..self.height {
+            for x in 0..WIDTH {
+                out.write_char(if self.get(x, y) { '1' } else { '0' })?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_chunks_covers_the_pages() {
+        assert_eq!(Framebuffer::new(32).all_chunks(), 0xffff_ffff);
+        assert_eq!(Framebuffer::new(64).all_chunks(), Chunks::MAX);
+        assert_eq!(Framebuffer::new(8).all_chunks(), 0xff);
+    }
+
+    #[test]
+    fn changed_chunks_finds_each_pixel() {
+        let blank = Framebuffer::new(32);
+        assert_eq!(blank.changed_chunks(&blank), 0);
+
+        let mut frame = Framebuffer::new(32);
+        frame.set(0, 0);
+        frame.set(17, 7);
+        frame.set(127, 31);
+        // Chunks 0 and 1 of the top page, and the last of the bottom one.
+        assert_eq!(frame.changed_chunks(&blank), 0b11 | 1 << 31);
+        assert_eq!(blank.changed_chunks(&frame), frame.changed_chunks(&blank));
+    }
+
+    #[test]
+    fn copy_chunk_copies_only_it() {
+        let mut frame = Framebuffer::new(64);
+        frame.fill_rect(0, 0, 128, 64);
+        let mut copy = Framebuffer::new(64);
+        copy.copy_chunk(&frame, 9);
+        assert_eq!(Framebuffer::chunk_location(9), (1, 16..32));
+        assert!(copy.get(16, 8) && copy.get(31, 15));
+        assert!(!copy.get(15, 8) && !copy.get(32, 8) && !copy.get(16, 7) && !copy.get(16, 16));
+        assert_eq!(frame.changed_chunks(&copy), frame.all_chunks() & !(1 << 9));
+    }
+}
@@ -0,0 +1,348 @@
+// Framing for messages between the Pico and the Arduino gadget board.
+//
+// Every message on the I2C bus, in either direction, is one frame:
+//
+//   version | type | sequence | payload length | payload... | CRC-8
+//
+// The CRC is CRC-8/SMBUS (polynomial 0x07, initial value 0) over
+// everything before it.  The sequence number counts frames sent by each
+// side, so repeated or stale frames can be spotted.  The Arduino's Wire
+// library buffers 32 bytes, which limits the frame size.
+//
+// Nothing here touches hardware, so host tools can share it.
+
+/// Bump when frames change incompatibly.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 4;
+const CRC_LEN: usize = 1;
+/// Longest frame either side sends or accepts.
+pub const MAX_FRAME_LEN: usize = 32;
+pub const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - HEADER_LEN - CRC_LEN;
+/// Length of a joystick sample frame, what the Pico reads.
+pub const JOYSTICK_FRAME_LEN: usize = HEADER_LEN + 5 + CRC_LEN;
+
+const TYPE_JOYSTICK_SAMPLE: u8 = 0x01;
+const TYPE_VERSION: u8 = 0x02;
+const TYPE_LCD_TEXT: u8 = 0x10;
+const TYPE_LCD_CLEAR: u8 = 0x11;
+const TYPE_LCD_CURSOR: u8 = 0x12;
+const TYPE_PING: u8 = 0x13;
+const TYPE_LCD_DEFINE_CHAR: u8 = 0x14;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Message<'a> {
+    /// Gadget to Pico: 10 bit ADC readings and the stick's push button.
+    JoystickSample { x: u16, y: u16, pressed: bool },
+    /// Gadget to Pico, in reply to `Ping`.
+    Version { protocol: u8 },
+    /// Pico to gadget: show text at the cursor.
+    LcdText(&'a [u8]),
+    /// Pico to gadget: blank the LCD and home the cursor.
+    LcdClear,
+    /// Pico to gadget: move the cursor.
+    LcdCursor { column: u8, row: u8 },
+    /// Pico to gadget: the next read returns `Version` instead of a sample.
+    Ping,
+    /// Pico to gadget: set the rows of custom character `code`, 0 to 7.
+    LcdDefineChar { code: u8, bitmap: [u8; 8] },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct Frame<'a> {
+    pub sequence: u8,
+    pub message: Message<'a>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum DecodeError {
+    /// Fewer bytes than the header and payload length need.
+    Truncated,
+    /// The other side speaks a different version.
+    Version(u8),
+    UnknownType(u8),
+    /// The payload length doesn't suit the message type.
+    Length,
+    Crc,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum EncodeError {
+    /// The message, or the buffer, is too small for the frame.
+    TooLong,
+}
+
+/// CRC-8/SMBUS, bitwise to save flash for a table.
+pub fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, b| {
+        (0..8).fold(crc ^ b, |crc, _| {
+            if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 }
+        })
+    })
+}
+
+/// Writes a frame into `buffer`, returning its length.
+pub fn encode(sequence: u8, message: &Message, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    let mut payload = [0u8; MAX_PAYLOAD_LEN];
+    let (message_type, payload_len) = match *message {
+        Message::JoystickSample { x, y, pressed } => {
+            payload[0..2].copy_from_slice(&x.to_be_bytes());
+            payload[2..4].copy_from_slice(&y.to_be_bytes());
+            payload[4] = u8::from(pressed);
+            (TYPE_JOYSTICK_SAMPLE, 5)
+        }
+        Message::Version { protocol } => {
+            payload[0] = protocol;
+            (TYPE_VERSION, 1)
+        }
+        Message::LcdText(text) => {
+            payload.get_mut(..text.len()).ok_or(EncodeError::TooLong)?.copy_from_slice(text);
+            (TYPE_LCD_TEXT, text.len())
+        }
+        Message::LcdClear => (TYPE_LCD_CLEAR, 0),
+        Message::LcdCursor { column, row } => {
+            payload[0] = column;
+            payload[1] = row;
+            (TYPE_LCD_CURSOR, 2)
+        }
+        Message::Ping => (TYPE_PING, 0),
+        Message::LcdDefineChar { code, bitmap } => {
+            payload[0] = code;
+            payload[1..9].copy_from_slice(&bitmap);
+            (TYPE_LCD_DEFINE_CHAR, 9)
+        }
+    };
+
+    let len = HEADER_LEN + payload_len + CRC_LEN;
+    let frame = buffer.get_mut(..len).ok_or(EncodeError::TooLong)?;
+    frame[0] = PROTOCOL_VERSION;
+    frame[1] = message_type;
+    frame[2] = sequence;
+    frame[3] = u8::try_from(payload_len).unwrap();
+    frame[HEADER_LEN..len - CRC_LEN].copy_from_slice(&payload[..payload_len]);
+    frame[len - CRC_LEN] = crc8(&frame[..len - CRC_LEN]);
+    Ok(len)
+}
+
+/// Reads a frame from the start of `buffer`, anything after it is ignored.
+/// Never panics, whatever the bytes.
+pub fn decode(buffer: &[u8]) -> Result<Frame<'_>, DecodeError> {
+    let header = buffer.get(..HEADER_LEN).ok_or(DecodeError::Truncated)?;
+    let payload_len = usize::from(header[3]);
+    if payload_len > MAX_PAYLOAD_LEN {
+        return Err(DecodeError::Length);
+    }
+    let len = HEADER_LEN + payload_len + CRC_LEN;
+    let frame = buffer.get(..len).ok_or(DecodeError::Truncated)?;
+    // Check the CRC first, a corrupted version byte is corruption, not a
+    // version mismatch.
+    if crc8(&frame[..len - CRC_LEN]) != frame[len - CRC_LEN] {
+        return Err(DecodeError::Crc);
+    }
+    if header[0] != PROTOCOL_VERSION {
+        return Err(DecodeError::Version(header[0]));
+    }
+
+    let payload = &frame[HEADER_LEN..len - CRC_LEN];
+    let expect_len = |expected: usize| if payload.len() == expected { Ok(()) } else { Err(DecodeError::Length) };
+    let message = match header[1] {
+        TYPE_JOYSTICK_SAMPLE => {
+            expect_len(5)?;
+            Message::JoystickSample {
+                x: u16::from_be_bytes([payload[0], payload[1]]),
+                y: u16::from_be_bytes([payload[2], payload[3]]),
+                pressed: payload[4] != 0,
+            }
+        }
+        TYPE_VERSION => {
+            expect_len(1)?;
+            Message::Version { protocol: payload[0] }
+        }
+        TYPE_LCD_TEXT => Message::LcdText(payload),
+        TYPE_LCD_CLEAR => {
+            expect_len(0)?;
+            Message::LcdClear
+        }
+        TYPE_LCD_CURSOR => {
+            expect_len(2)?;
+            Message::LcdCursor { column: payload[0], row: payload[1] }
+        }
+        TYPE_PING => {
+            expect_len(0)?;
+            Message::Ping
+        }
+        TYPE_LCD_DEFINE_CHAR => {
+            expect_len(9)?;
+            Message::LcdDefineChar { code: payload[0], bitmap: payload[1..9].try_into().unwrap() }
+        }
+        other => return Err(DecodeError::UnknownType(other)),
+    };
+
+    Ok(Frame { sequence: header[2], message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: [Message<'static>; 10] = [
+        Message::JoystickSample { x: 0, y: 1023, pressed: true },
+        Message::JoystickSample { x: 0xabcd, y: 0x1234, pressed: false },
+        Message::Version { protocol: PROTOCOL_VERSION },
+        Message::LcdText(b""),
+        // The longest text that fits.
+        Message::LcdText(b"Hello from the Pico, 27 byt"),
+        Message::LcdClear,
+        Message::LcdCursor { column: 15, row: 1 },
+        Message::Ping,
+        Message::LcdDefineChar { code: 7, bitmap: [0x00, 0x0a, 0x1f, 0x1f, 0x0e, 0x04, 0x00, 0xff] },
+        Message::LcdDefineChar { code: 0, bitmap: [0; 8] },
+    ];
+
+    fn encoded(sequence: u8, message: &Message) -> Vec<u8> {
+        let mut buffer = [0u8; MAX_FRAME_LEN];
+        let len = encode(sequence, message, &mut buffer).unwrap();
+        buffer[..len].to_vec()
+    }
+
+    /// Recomputes the CRC after a frame has been tampered with.
+    fn reseal(frame: &mut [u8]) {
+        let len = frame.len();
+        frame[len - CRC_LEN] = crc8(&frame[..len - CRC_LEN]);
+    }
+
+    #[test]
+    fn crc_matches_smbus_check_value() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for (i, message) in MESSAGES.iter().enumerate() {
+            let sequence = u8::try_from(i).unwrap().wrapping_mul(37);
+            let frame = encoded(sequence, message);
+            assert!(frame.len() <= MAX_FRAME_LEN);
+            assert_eq!(decode(&frame), Ok(Frame { sequence, message: *message }));
+        }
+    }
+
+    #[test]
+    fn joystick_sample_has_expected_length() {
+        let frame = encoded(0, &Message::JoystickSample { x: 512, y: 512, pressed: false });
+        assert_eq!(frame.len(), JOYSTICK_FRAME_LEN);
+    }
+
+    #[test]
+    fn trailing_bytes_are_ignored() {
+        let mut frame = encoded(9, &Message::Ping);
+        frame.extend([0xff; 10]);
+        assert_eq!(decode(&frame), Ok(Frame { sequence: 9, message: Message::Ping }));
+    }
+
+    #[test]
+    fn too_long_to_encode() {
+        let text = [b'x'; MAX_PAYLOAD_LEN + 1];
+        let mut buffer = [0u8; 64];
+        assert_eq!(encode(0, &Message::LcdText(&text), &mut buffer), Err(EncodeError::TooLong));
+        let mut small = [0u8; HEADER_LEN];
+        assert_eq!(encode(0, &Message::Ping, &mut small), Err(EncodeError::TooLong));
+        // Exactly the longest text fits.
+        assert_eq!(encode(0, &Message::LcdText(&text[1..]), &mut buffer), Ok(MAX_FRAME_LEN));
+    }
+
+    #[test]
+    fn corrupt_crc() {
+        for message in MESSAGES {
+            let mut frame = encoded(1, &message);
+            // Flip a bit anywhere, including in the CRC itself.  Except the
+            // length, which moves the CRC, see `truncated` and `wrong_length`.
+            for i in 0..frame.len() * 8 {
+                frame[i / 8] ^= 1 << (i % 8);
+                if i / 8 != 3 {
+                    assert_eq!(decode(&frame), Err(DecodeError::Crc), "{message:?} bit {i}");
+                }
+                frame[i / 8] ^= 1 << (i % 8);
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_version() {
+        let mut frame = encoded(1, &Message::Ping);
+        frame[0] = PROTOCOL_VERSION + 1;
+        reseal(&mut frame);
+        assert_eq!(decode(&frame), Err(DecodeError::Version(PROTOCOL_VERSION + 1)));
+    }
+
+    #[test]
+    fn wrong_length() {
+        // One byte too many and too few for each fixed size message.
+        for message in MESSAGES.iter().filter(|m| !matches!(m, Message::LcdText(_))) {
+            let frame = encoded(1, message);
+            let mut longer = frame.clone();
+            longer[3] += 1;
+            longer.insert(frame.len() - CRC_LEN, 0);
+            reseal(&mut longer);
+            assert_eq!(decode(&longer), Err(DecodeError::Length), "{message:?}");
+
+            if frame[3] > 0 {
+                let mut shorter = frame.clone();
+                shorter[3] -= 1;
+                shorter.remove(frame.len() - CRC_LEN - 1);
+                reseal(&mut shorter);
+                assert_eq!(decode(&shorter), Err(DecodeError::Length), "{message:?}");
+            }
+        }
+        // Longer than any frame.
+        let mut frame = vec![PROTOCOL_VERSION, TYPE_LCD_TEXT, 0, u8::try_from(MAX_PAYLOAD_LEN + 1).unwrap()];
+        frame.extend([0; MAX_PAYLOAD_LEN + 2]);
+        assert_eq!(decode(&frame), Err(DecodeError::Length));
+    }
+
+    #[test]
+    fn truncated() {
+        let frame = encoded(1, &Message::LcdCursor { column: 1, row: 2 });
+        for len in 0..frame.len() {
+            assert_eq!(decode(&frame[..len]), Err(DecodeError::Truncated), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn unknown_type() {
+        let mut frame = encoded(1, &Message::Ping);
+        frame[1] = 0x7f;
+        reseal(&mut frame);
+        assert_eq!(decode(&frame), Err(DecodeError::UnknownType(0x7f)));
+    }
+
+    #[test]
+    fn decode_never_panics() {
+        // xorshift32 from a fixed seed, so failures can be reproduced.
+        let mut state: u32 = 0x9e37_79b9;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let mut buffer = [0u8; MAX_FRAME_LEN + 8];
+        for _ in 0..200_000 {
+            let len = next() as usize % buffer.len();
+            buffer.iter_mut().for_each(|b| *b = next() as u8);
+            // Mostly random bytes, but often with a plausible header and a
+            // valid CRC so decoding gets past the early checks.
+            let r = next();
+            if r & 1 != 0 {
+                buffer[0] = PROTOCOL_VERSION;
+            }
+            if r & 2 != 0 {
+                buffer[3] %= u8::try_from(MAX_PAYLOAD_LEN + 2).unwrap();
+            }
+            let frame_len = HEADER_LEN + usize::from(buffer[3]) + CRC_LEN;
+            if r & 4 != 0 && frame_len <= len {
+                reseal(&mut buffer[..frame_len]);
+            }
+            let _ = decode(&buffer[..len]);
+        }
+    }
+}
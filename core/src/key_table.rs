@@ -0,0 +1,405 @@
+use usbd_human_interface_device::page::{Consumer, Desktop, Keyboard};
+use Keyboard::*;
+
+use KeyFunction::*;
+
+pub const KEY_ROWS: usize = 6;
+pub const KEY_COLUMNS: usize = 17;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum MouseButton {
+    #[default]
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl MouseButton {
+    /// The button's bit in `WheelMouseReport::buttons`.
+    pub fn bit(self) -> u8 {
+        match self {
+            MouseButton::Left => 0x01,
+            MouseButton::Right => 0x02,
+            MouseButton::Middle => 0x04,
+            MouseButton::Back => 0x08,
+            MouseButton::Forward => 0x10,
+        }
+    }
+
+    /// The next button, for cycling through them from a key.
+    pub fn next(self) -> Self {
+        match self {
+            MouseButton::Left => MouseButton::Right,
+            MouseButton::Right => MouseButton::Middle,
+            MouseButton::Middle => MouseButton::Back,
+            MouseButton::Back => MouseButton::Forward,
+            MouseButton::Forward => MouseButton::Left,
+        }
+    }
+}
+
+/// Direction of mouse key movement and scrolling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Ways of using the joystick other than moving the pointer, active while
+/// a `Hold` key is held or after a `Toggle` key turns them on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PointerMode {
+    /// The joystick scrolls vertically and horizontally.
+    Scroll,
+    /// The pointer moves slower, for fine work.
+    Precision,
+    /// The joystick is an analog stick on the gamepad device instead of
+    /// moving the pointer.
+    Gamepad,
+    /// The joystick positions the pointer on the screen through the
+    /// digitizer device.
+    Absolute,
+}
+
+/// The keys of the System Control device, the usages its descriptor covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SystemKey {
+    PowerDown,
+    Sleep,
+    WakeUp,
+}
+
+impl SystemKey {
+    /// The key's usage on the Generic Desktop page.
+    pub fn usage(self) -> Desktop {
+        match self {
+            SystemKey::PowerDown => Desktop::SystemPowerDown,
+            SystemKey::Sleep => Desktop::SystemSleep,
+            SystemKey::WakeUp => Desktop::SystemWakeUp,
+        }
+    }
+}
+
+/// Things the keyboard does itself, triggered once when the key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Action {
+    /// Switch between NKRO and 6KRO boot keyboard modes.  Saved to flash,
+    /// and the keyboard reboots so the host sees the new descriptor.
+    ToggleRollover,
+    /// Select the next pointer acceleration curve.
+    CycleMouseCurve,
+    MouseSensitivityUp,
+    MouseSensitivityDown,
+    MouseMaxSpeedUp,
+    MouseMaxSpeedDown,
+    /// Take the joystick's current position as its centre.
+    RecalibrateJoystick,
+    /// Start the guided joystick calibration, see `Calibrator`.
+    CalibrateJoystick,
+    /// Turn a pointer mode on or off.
+    Toggle(PointerMode),
+    /// Select the next speed divisor for precision mode.
+    CyclePrecisionDivisor,
+    /// Turn the joystick's axes clockwise by this many degrees.
+    RotateJoystick(i16),
+    SwapJoystickAxes,
+    InvertJoystickX,
+    InvertJoystickY,
+    /// Make where the joystick is pointing in absolute mode the new centre.
+    SetAbsoluteCentre,
+    /// Select the next joystick smoothing filter.
+    CycleFilter,
+    /// Switch between a radial and an axial deadzone.
+    CycleDeadzoneShape,
+    DeadzoneRadiusUp,
+    DeadzoneRadiusDown,
+    /// Select which mouse button the joystick's push button clicks.
+    CycleJoystickButton,
+    /// Change how far the pointer moves before the `Dual` keys click.
+    AutoMouseDistanceUp,
+    AutoMouseDistanceDown,
+    /// Change how long mouse mode lasts without the mouse being used.
+    AutoMouseTimeoutUp,
+    AutoMouseTimeoutDown,
+    /// Hold a mouse button down until the key is pressed again, for
+    /// dragging without keeping a key held.
+    DragLock(MouseButton),
+}
+
+
+pub enum KeyFunction {
+    Nothing,
+    // On the Fn layer, use whatever the key does on the base layer.
+    Transparent,
+    // Selects FN_KEY_MAPPING while held.
+    Fn,
+    Key(Keyboard),
+    MultiKey(&'static [Keyboard]),
+    Media(Consumer),
+    System(SystemKey),
+    // Some buttons are dual function, acting either as a keyboard button
+    // or as a mouse button.
+    Dual(Keyboard, MouseButton),
+    // Mouse keys, see `MouseKeys`.
+    MouseMove(Direction),
+    MouseWheel(Direction),
+    MouseClick(MouseButton),
+    // Gamepad button, numbered from 1, only sent in gamepad mode.
+    GamepadButton(u8),
+    // A pointer mode which is active while the key is held.
+    Hold(PointerMode),
+    Action(Action)
+}
+
+/// Most keys on the keyboard will return the keyboard from mousish
+/// mode back to normal mode, _except_ for this.
+/// 
+/// In other words, these keys are the ones we expect to be used as
+/// modifiers along with a mouse click.
+pub const MOUSE_MODIFIER_KEYS: [Keyboard; 4] = [
+    LeftShift,
+    LeftControl,
+    LeftGUI,
+    LeftAlt
+];
+
+pub const KEY_MAPPING: [[KeyFunction; KEY_COLUMNS]; KEY_ROWS] = [
+    [
+        // I bind "meta+I" to mute/unmute in GNOME.
+        MultiKey(&[Keyboard::LeftGUI, Keyboard::I]), // magic E
+        Key(LeftControl),  //
+        Key(LeftGUI),      //
+        Key(LeftAlt),      //
+        Nothing,           // not wired
+        Nothing,           // not wired
+        Dual(Space, MouseButton::Left),        //
+        Nothing,           // not wired
+        Nothing,           // not wired
+        Dual(Space, MouseButton::Right),        //
+        Nothing,           // not wired
+        Key(RightAlt),     //
+        Key(RightGUI),     //
+        Key(RightControl), //
+        Key(LeftArrow),    //
+        Key(DownArrow),    //
+        Key(RightArrow),   //
+    ],
+    [
+        Fn,                  // magic D
+        Key(LeftShift),      //
+        Key(NonUSBackslash), //
+        Key(Z),              //
+        Key(X),              //
+        Key(C),              //
+        Key(V),              //
+        Key(B),              //
+        Key(N),              //
+        Key(M),              //
+        Key(Comma),          //
+        Key(Dot),            //
+        Key(ForwardSlash),   //
+        Nothing,             // not wired
+        Key(RightShift),     //
+        Key(UpArrow),        //
+        Key(PageDown),       //
+    ],
+    [
+        System(SystemKey::Sleep),              // magic C
+        Key(CapsLock),   //
+        Nothing,         // not wired
+        Key(A),          //
+        Key(S),          //
+        Key(D),          //
+        Key(F),          //
+        Key(G),          //
+        Key(H),          //
+        Key(J),          //
+        Key(K),          //
+        Key(L),          //
+        Key(Semicolon),  //
+        Key(Apostrophe), //
+        Key(NonUSHash),  //
+        Nothing,         // not wired
+        Key(PageUp),     //
+    ],
+    [
+        Media(Consumer::Mute), // magic B
+        Key(Tab),                   //
+        Nothing,                    // not wired
+        Key(Q),                     //
+        Key(W),                     //
+        Key(E),                     //
+        Key(R),                     //
+        Key(T),                     //
+        Key(Y),                     //
+        Key(U),                     //
+        Key(I),                     //
+        Key(O),                     //
+        Key(P),                     //
+        Key(LeftBrace),             //
+        Key(RightBrace),            //
+        Key(ReturnEnter),           //
+        Key(End),                   //
+    ],
+    [
+        Media(Consumer::PlayPause), // Magic a
+        Key(Grave),            //
+        Key(Keyboard1),        //
+        Key(Keyboard2),        //
+        Key(Keyboard3),        //
+        Key(Keyboard4),        //
+        Key(Keyboard5),        //
+        Key(Keyboard6),        //
+        Key(Keyboard7),        //
+        Key(Keyboard8),        //
+        Key(Keyboard9),        //
+        Key(Keyboard0),        //
+        Key(Minus),            //
+        Key(Equal),            //
+        Nothing,               // not wired
+        Key(DeleteBackspace),  //
+        Key(Home),             //
+    ],
+    [
+        Nothing,                          // not wired
+        Key(Escape),                      //
+        Key(F1),                          //
+        Key(F2),                          //
+        Key(F3),                          //
+        Key(F4),                          //
+        Key(F5),                          //
+        Key(F6),                          //
+        Key(F7),                          //
+        Key(F8),                          //
+        Key(F9),                          //
+        Key(F10),                         //
+        Key(F11),                         //
+        Key(F12),                         //
+        Media(Consumer::VolumeDecrement), //
+        Media(Consumer::VolumeIncrement), //
+        Key(DeleteForward),               //
+    ],
+];
+
+/// Functions of keys pressed while the Fn key is held.  A key keeps the
+/// function it was pressed with until it is released.
+pub const FN_KEY_MAPPING: [[KeyFunction; KEY_COLUMNS]; KEY_ROWS] = [
+    [
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Hold(PointerMode::Scroll), // left space
+        Transparent,
+        Transparent,
+        Hold(PointerMode::Precision), // right space
+        Transparent,
+        MouseClick(MouseButton::Left), // right alt
+        MouseClick(MouseButton::Middle), // right gui
+        MouseClick(MouseButton::Right), // right control
+        MouseMove(Direction::Left), // left arrow
+        MouseMove(Direction::Down), // down arrow
+        MouseMove(Direction::Right), // right arrow
+    ],
+    [
+        Transparent,
+        Transparent,
+        Transparent,
+        Action(Action::CycleDeadzoneShape), // Z
+        Action(Action::DeadzoneRadiusDown), // X
+        Action(Action::DeadzoneRadiusUp), // C
+        Action(Action::AutoMouseDistanceDown), // V
+        Action(Action::AutoMouseDistanceUp), // B
+        Action(Action::AutoMouseTimeoutDown), // N
+        Action(Action::AutoMouseTimeoutUp), // M
+        MouseClick(MouseButton::Back), // comma
+        MouseClick(MouseButton::Forward), // dot
+        Transparent,
+        Transparent,
+        Action(Action::DragLock(MouseButton::Left)), // right shift
+        MouseMove(Direction::Up), // up arrow
+        MouseWheel(Direction::Down), // page down
+    ],
+    [
+        System(SystemKey::PowerDown), // magic C
+        Action(Action::SetAbsoluteCentre), // caps lock
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        MouseWheel(Direction::Up), // page up
+    ],
+    [
+        System(SystemKey::WakeUp), // magic B
+        Action(Action::Toggle(PointerMode::Absolute)), // tab
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Transparent,
+        Action(Action::SwapJoystickAxes), // P
+        Action(Action::RotateJoystick(-15)), // left brace
+        Action(Action::RotateJoystick(15)), // right brace
+        Transparent,
+        MouseWheel(Direction::Right), // end
+    ],
+    [
+        Transparent,
+        Transparent,
+        GamepadButton(1), // 1
+        GamepadButton(2), // 2
+        GamepadButton(3), // 3
+        GamepadButton(4), // 4
+        GamepadButton(5), // 5
+        GamepadButton(6), // 6
+        GamepadButton(7), // 7
+        GamepadButton(8), // 8
+        GamepadButton(9), // 9
+        GamepadButton(10), // 0
+        Action(Action::InvertJoystickX), // minus
+        Action(Action::InvertJoystickY), // equal
+        Transparent,
+        Transparent,
+        MouseWheel(Direction::Left), // home
+    ],
+    [
+        Transparent,
+        Action(Action::Toggle(PointerMode::Gamepad)), // escape
+        Action(Action::CycleMouseCurve), // F1
+        Action(Action::MouseSensitivityDown), // F2
+        Action(Action::MouseSensitivityUp), // F3
+        Action(Action::MouseMaxSpeedDown), // F4
+        Action(Action::MouseMaxSpeedUp), // F5
+        Action(Action::RecalibrateJoystick), // F6
+        Action(Action::CalibrateJoystick), // F7
+        Action(Action::Toggle(PointerMode::Scroll)), // F8
+        Action(Action::Toggle(PointerMode::Precision)), // F9
+        Action(Action::CyclePrecisionDivisor), // F10
+        Action(Action::CycleJoystickButton), // F11
+        Action(Action::ToggleRollover), // F12
+        Transparent,
+        Transparent,
+        Action(Action::CycleFilter), // delete
+    ],
+];
@@ -0,0 +1,41 @@
+// Keyboard and pointer logic for the firmware, everything which doesn't
+// need the RP2040 or USB.  It builds for the host as well, which is where
+// the tests run: `cargo test` in this directory.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod absolute;
+pub mod acceleration;
+pub mod auto_mouse;
+pub mod calibration;
+pub mod deadzone;
+pub mod debounce;
+pub mod filter;
+pub mod framebuffer;
+pub mod gadget_protocol;
+pub mod key_table;
+pub mod mouse;
+pub mod mouse_keys;
+pub mod orientation;
+pub mod report_queue;
+pub mod settings;
+pub mod status_picture;
+pub mod status_screen;
+pub mod text_display;
+pub mod typing_speed;
+
+// Tests have nowhere to send log messages, they go nowhere.
+#[cfg(test)]
+mod discard_logger {
+    #[defmt::global_logger]
+    struct DiscardLogger;
+
+    unsafe impl defmt::Logger for DiscardLogger {
+        fn acquire() {}
+        unsafe fn flush() {}
+        unsafe fn release() {}
+        unsafe fn write(_bytes: &[u8]) {}
+    }
+
+    defmt::timestamp!("");
+}
@@ -0,0 +1,383 @@
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+
+use crate::acceleration::Accelerator;
+use crate::acceleration::PointerSettings;
+use crate::acceleration::SCROLL_SPEED;
+use crate::calibration::CalibrationError;
+use crate::calibration::Calibrator;
+use crate::calibration::JoystickCalibration;
+use crate::deadzone::DriftCompensator;
+use crate::filter::JitterFilter;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Point2D<T> {
+    pub x: T,
+    pub y: T
+}
+
+pub type ButtonState = bool;
+
+/// Period between reading the joystick and sending mouse reports.
+pub const MOUSE_REPORT_PERIOD_MS: u32 = 10;
+
+/// Fractions of a pixel or wheel detent we track movement in, so slow
+/// movement still accumulates into whole steps.
+pub const SUBPIXELS: i64 = 1 << 16;
+
+/// Subpixels in one count of a wheel report, when the host divides each
+/// detent into `multiplier` counts.
+pub fn wheel_unit(multiplier: u8) -> i64 {
+    SUBPIXELS / i64::from(multiplier.max(1))
+}
+
+pub const UPDATES_PER_SECOND: i64 = 1000 / MOUSE_REPORT_PERIOD_MS as i64;
+
+/// Wheel counts per detent the host has asked for, one until it sets the
+/// Resolution Multiplier.
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct WheelMultiplier {
+    pub vertical: u8,
+    pub horizontal: u8,
+}
+
+impl Default for WheelMultiplier {
+    fn default() -> Self {
+        Self { vertical: 1, horizontal: 1 }
+    }
+}
+
+/// Adds two sources' reports into the one sent.  Whatever doesn't fit is
+/// taken off `b`, so each source accounting its own report only deducts
+/// what was sent and the rest goes in a later report.
+pub fn merge_mouse_reports(a: &WheelMouseReport, b: &mut WheelMouseReport) -> WheelMouseReport {
+    // Both fit in an i8, so when the sum doesn't they have the same sign
+    // and the excess is no bigger than b.
+    let merge = |a: i8, b: &mut i8| {
+        let sum = a.saturating_add(*b);
+        *b = sum - a;
+        sum
+    };
+    WheelMouseReport {
+        buttons: a.buttons | b.buttons,
+        x: merge(a.x, &mut b.x),
+        y: merge(a.y, &mut b.y),
+        vertical_wheel: merge(a.vertical_wheel, &mut b.vertical_wheel),
+        horizontal_wheel: merge(a.horizontal_wheel, &mut b.horizontal_wheel),
+    }
+}
+
+#[derive(Default)]
+pub struct MouseTracker {
+    unreported_movement: Point2D<i64>,
+    // Scrolling in fractions of a wheel detent, y is up.
+    unreported_scroll: Point2D<i64>,
+    button: ButtonState,
+    origin: Option<Point2D<i16>>,
+    accelerator: Accelerator,
+    scroll_accelerator: Accelerator,
+    // The joystick scrolls rather than moving the cursor.
+    scrolling: bool,
+    // The cursor moves slower, see `PointerSettings::precision_divisor`.
+    precise: bool,
+    // The joystick's position is reported as it is, by the gamepad or
+    // digitizer, and it doesn't move the cursor.
+    analog: bool,
+    // Latest offset from the centre, after the deadzone.
+    deflection: Point2D<i16>,
+    drift: DriftCompensator,
+    filter: JitterFilter,
+    // Some while a calibration is running, the cursor doesn't move.
+    calibrator: Option<Calibrator>,
+}
+
+impl MouseTracker {
+    // Update from a joystick reading.
+    // `reset` takes the current position as the new centre.
+    // Returns the result of a calibration when one finishes.
+    pub fn update(
+        &mut self,
+        raw: (Point2D<i16>, ButtonState),
+        reset: bool,
+        settings: &PointerSettings
+    ) -> Option<Result<JoystickCalibration, CalibrationError>> {
+        self.button = raw.1;
+
+        if let Some(calibrator) = self.calibrator.as_mut() {
+            let result = calibrator.update(&raw.0);
+            if result.is_some() {
+                self.calibrator = None;
+                // Readings will be scaled differently from now on.
+                self.origin = None;
+                self.filter.reset();
+            }
+            return result;
+        }
+
+        let raw = match &settings.calibration {
+            Some(calibration) => calibration.normalize(&raw.0),
+            None => raw.0,
+        };
+        // After calibration, which is in the stick's own axes.
+        let raw = settings.orientation.apply(&raw);
+        let raw = self.filter.apply(settings.filter, &raw);
+        if self.origin.is_none() || reset {
+            self.origin = Some(raw);
+            self.drift.restart(&raw);
+        }
+
+        if let Some(origin) = self.drift.update(&raw, self.origin.as_ref().unwrap(), &settings.deadzone) {
+            defmt::debug!("Joystick recentred to {}, {}", origin.x, origin.y);
+            self.origin = Some(origin);
+        }
+
+        let origin = self.origin.as_ref().unwrap();
+        let (dx, dy) = settings.deadzone.apply(
+            i32::from(raw.x - origin.x),
+            i32::from(raw.y - origin.y),
+        );
+        self.deflection = Point2D {
+            x: i16::try_from(dx.clamp(i16::MIN.into(), i16::MAX.into())).unwrap(),
+            y: i16::try_from(dy.clamp(i16::MIN.into(), i16::MAX.into())).unwrap(),
+        };
+
+        if self.analog {
+            return None;
+        }
+        if self.scrolling {
+            let (vx, vy) = self.scroll_accelerator.velocity(
+                &SCROLL_SPEED,
+                dx,
+                dy,
+                SUBPIXELS,
+                UPDATES_PER_SECOND,
+            );
+            // Pushing the stick up scrolls up, the wheel counts up as positive.
+            self.unreported_scroll.x += vx;
+            self.unreported_scroll.y -= vy;
+        } else {
+            let (vx, vy) = self.accelerator.velocity(
+                &settings.pointer_speed(self.precise),
+                dx,
+                dy,
+                SUBPIXELS,
+                UPDATES_PER_SECOND,
+            );
+            self.unreported_movement.x += vx;
+            self.unreported_movement.y += vy;
+        }
+        None
+    }
+
+    /// Switch between moving the cursor and scrolling.  Leftover fractions
+    /// are dropped so they don't leak into the other mode.
+    pub fn set_scrolling(&mut self, scrolling: bool) {
+        if scrolling != self.scrolling {
+            self.scrolling = scrolling;
+            self.unreported_movement = Point2D::default();
+            self.unreported_scroll = Point2D::default();
+        }
+    }
+
+    /// Slow the cursor down for fine work.  Movement still accumulates in
+    /// subpixels, so the slowest speeds step one pixel at a time.
+    pub fn set_precision(&mut self, precise: bool) {
+        self.precise = precise;
+    }
+
+    /// Use the joystick's position rather than moving the cursor, see `deflection`.
+    pub fn set_analog(&mut self, analog: bool) {
+        if analog != self.analog {
+            self.analog = analog;
+            self.unreported_movement = Point2D::default();
+            self.unreported_scroll = Point2D::default();
+        }
+    }
+
+    /// Forgets the stick, for when the gadget board goes away.  The next
+    /// reading is taken as the centre, the stick may have moved meanwhile.
+    pub fn restart(&mut self) {
+        self.origin = None;
+        self.button = false;
+        self.deflection = Point2D::default();
+        self.unreported_movement = Point2D::default();
+        self.unreported_scroll = Point2D::default();
+        self.filter.reset();
+    }
+
+    /// Offset of the stick from its centre, in calibrated counts.
+    pub fn deflection(&self) -> &Point2D<i16> {
+        &self.deflection
+    }
+
+    pub fn button(&self) -> ButtonState {
+        self.button
+    }
+
+    /// Start a guided calibration, see `Calibrator`.
+    pub fn start_calibration(&mut self) {
+        self.calibrator = Some(Calibrator::default());
+        self.unreported_movement = Point2D::default();
+        self.unreported_scroll = Point2D::default();
+    }
+
+    pub fn populate_report(&self, report: &mut WheelMouseReport, settings: &PointerSettings, wheel: &WheelMultiplier) {
+        let rx = (self.unreported_movement.x / SUBPIXELS).clamp(i8::MIN.into(), i8::MAX.into());
+        let ry = (self.unreported_movement.y / SUBPIXELS).clamp(i8::MIN.into(), i8::MAX.into());
+
+        let sx = (self.unreported_scroll.x / wheel_unit(wheel.horizontal)).clamp(i8::MIN.into(), i8::MAX.into());
+        let sy = (self.unreported_scroll.y / wheel_unit(wheel.vertical)).clamp(i8::MIN.into(), i8::MAX.into());
+
+        report.x = i8::try_from(rx).unwrap();
+        report.y = i8::try_from(ry).unwrap();
+        report.horizontal_wheel = i8::try_from(sx).unwrap();
+        report.vertical_wheel = i8::try_from(sy).unwrap();
+        // In gamepad or absolute mode the button is reported there instead.
+        report.buttons = if self.button && !self.analog { settings.joystick_button.bit() } else { 0x0 };
+    }
+
+    pub fn account_report(&mut self, report: &WheelMouseReport, wheel: &WheelMultiplier) {
+        self.unreported_movement.x -= i64::from(report.x) * SUBPIXELS;
+        self.unreported_movement.y -= i64::from(report.y) * SUBPIXELS;
+        self.unreported_scroll.x -= i64::from(report.horizontal_wheel) * wheel_unit(wheel.horizontal);
+        self.unreported_scroll.y -= i64::from(report.vertical_wheel) * wheel_unit(wheel.vertical);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTRE: Point2D<i16> = Point2D { x: 512, y: 500 };
+
+    fn at(dx: i16, dy: i16) -> (Point2D<i16>, ButtonState) {
+        (Point2D { x: CENTRE.x + dx, y: CENTRE.y + dy }, false)
+    }
+
+    /// Feeds a trace of readings through the tracker, sending a report
+    /// after each like the firmware does.  The first reading is the centre.
+    fn run(tracker: &mut MouseTracker, trace: &[(Point2D<i16>, ButtonState)]) -> Vec<WheelMouseReport> {
+        let settings = PointerSettings::default();
+        let wheel = WheelMultiplier::default();
+        trace
+            .iter()
+            .map(|reading| {
+                tracker.update(*reading, false, &settings);
+                let mut report = WheelMouseReport::default();
+                tracker.populate_report(&mut report, &settings, &wheel);
+                tracker.account_report(&report, &wheel);
+                report
+            })
+            .collect()
+    }
+
+    fn total(reports: &[WheelMouseReport]) -> (i32, i32) {
+        reports.iter().fold((0, 0), |(x, y), r| (x + i32::from(r.x), y + i32::from(r.y)))
+    }
+
+    fn length(x: i32, y: i32) -> i32 {
+        crate::acceleration::isqrt((x * x + y * y) as u64) as i32
+    }
+
+    #[test]
+    fn jitter_inside_deadzone_does_not_move() {
+        let trace = [at(0, 0), at(3, -2), at(-5, 4), at(8, 8), at(-11, 0), at(0, 12), at(1, 1)];
+        let reports = run(&mut MouseTracker::default(), &trace);
+        assert!(reports.iter().all(|r| *r == WheelMouseReport::default()));
+    }
+
+    #[test]
+    fn full_deflection_reaches_max_speed() {
+        // One second pushed right, past full deflection once the 12 count
+        // deadzone is taken off.
+        let mut trace = vec![at(0, 0)];
+        trace.extend([at(500, 0); UPDATES_PER_SECOND as usize]);
+        let reports = run(&mut MouseTracker::default(), &trace);
+        // 640 pixels per second, less the fraction still unreported.
+        assert_eq!(total(&reports), (639, 0));
+        // 6.4 pixels per update, spread evenly.
+        assert!(reports[1..].iter().all(|r| r.x == 6 || r.x == 7));
+    }
+
+    #[test]
+    fn slow_movement_accumulates() {
+        // Just outside the deadzone, well under a pixel per update.
+        let mut trace = vec![at(0, 0)];
+        trace.extend([at(0, -20); 50]);
+        let reports = run(&mut MouseTracker::default(), &trace);
+        // 8 counts of 480 at 640 pixels per second, for half a second.
+        assert_eq!(total(&reports), (0, -5));
+        assert!(reports.iter().all(|r| r.y >= -1));
+    }
+
+    #[test]
+    fn diagonal_moves_at_same_speed() {
+        let mut trace = vec![at(0, 0)];
+        trace.extend([at(240, 0); 20]);
+        let straight = total(&run(&mut MouseTracker::default(), &trace));
+
+        // The same distance from centre, at 45 degrees.
+        let mut trace = vec![at(0, 0)];
+        trace.extend([at(170, 170); 20]);
+        let (x, y) = total(&run(&mut MouseTracker::default(), &trace));
+        assert_eq!(x, y);
+        let diagonal = length(x, y);
+        assert!((diagonal - straight.0).abs() <= 2, "{diagonal} vs {}", straight.0);
+    }
+
+    #[test]
+    fn returning_to_centre_stops() {
+        let mut trace = vec![at(0, 0)];
+        trace.extend([at(300, 0); 10]);
+        trace.extend([at(0, 0); 10]);
+        let reports = run(&mut MouseTracker::default(), &trace);
+        assert!(reports[..11].iter().skip(1).all(|r| r.x > 0));
+        // At most one leftover pixel after letting go.
+        assert!(reports[11..].iter().map(|r| i32::from(r.x)).sum::<i32>() <= 1);
+        assert!(reports[12..].iter().all(|r| r.x == 0));
+    }
+
+    #[test]
+    fn scrolling_uses_the_wheel() {
+        let mut tracker = MouseTracker::default();
+        tracker.set_scrolling(true);
+        let mut trace = vec![at(0, 0)];
+        // Pushed fully up, for a second.
+        trace.extend([at(0, -500); UPDATES_PER_SECOND as usize]);
+        let reports = run(&mut tracker, &trace);
+        assert_eq!(total(&reports), (0, 0));
+        let detents: i32 = reports.iter().map(|r| i32::from(r.vertical_wheel)).sum();
+        // SCROLL_SPEED's 40 detents per second, up is positive.
+        assert!((39..=40).contains(&detents), "{detents}");
+        assert!(reports.iter().all(|r| r.horizontal_wheel == 0));
+    }
+
+    #[test]
+    fn merge_keeps_what_does_not_fit() {
+        let joystick = WheelMouseReport { x: 100, y: -100, vertical_wheel: 3, ..Default::default() };
+        let mut keys = WheelMouseReport { x: 50, y: -50, vertical_wheel: 2, ..Default::default() };
+        let merged = merge_mouse_reports(&joystick, &mut keys);
+        assert_eq!((merged.x, merged.y, merged.vertical_wheel), (127, -128, 5));
+        // Only what was sent is accounted.
+        assert_eq!((keys.x, keys.y, keys.vertical_wheel), (27, -28, 2));
+
+        let mut keys = WheelMouseReport { x: i8::MAX, y: i8::MIN, ..Default::default() };
+        let full = WheelMouseReport { x: i8::MAX, y: i8::MIN, ..Default::default() };
+        let merged = merge_mouse_reports(&full, &mut keys);
+        assert_eq!((merged.x, merged.y), (i8::MAX, i8::MIN));
+        assert_eq!((keys.x, keys.y), (0, 0));
+
+        // Opposite signs always fit.
+        let mut keys = WheelMouseReport { x: i8::MIN, ..Default::default() };
+        let merged = merge_mouse_reports(&full, &mut keys);
+        assert_eq!((merged.x, keys.x), (-1, i8::MIN));
+    }
+
+    #[test]
+    fn button_follows_reading() {
+        let trace = [at(0, 0), (CENTRE, true), (CENTRE, true), (CENTRE, false)];
+        let reports = run(&mut MouseTracker::default(), &trace);
+        let buttons: Vec<u8> = reports.iter().map(|r| r.buttons).collect();
+        let left = PointerSettings::default().joystick_button.bit();
+        assert_eq!(buttons, [0, left, left, 0]);
+    }
+}
@@ -0,0 +1,95 @@
+// Mouse keys: moving the cursor and scrolling from the keyboard, so there
+// is a usable pointer without the joystick.
+//
+// Movement starts slowly and ramps up while the keys are held, like mouse
+// keys on most keyboards.
+
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+
+use crate::key_table::Direction;
+use crate::mouse::wheel_unit;
+use crate::mouse::Point2D;
+use crate::mouse::SUBPIXELS;
+use crate::mouse::UPDATES_PER_SECOND;
+use crate::mouse::WheelMultiplier;
+use crate::mouse::MOUSE_REPORT_PERIOD_MS;
+
+/// Cursor speed when a movement key is first pressed, pixels per second.
+const START_SPEED: i64 = 100;
+/// Cursor speed once the ramp is finished, pixels per second.
+const MAX_SPEED: i64 = 1200;
+/// How long movement keys must be held to reach `MAX_SPEED`.
+const RAMP_UPDATES: i64 = 1500 / MOUSE_REPORT_PERIOD_MS as i64;
+/// Scrolling speed while a wheel key is held, detents per second.  The
+/// first detent is sent as soon as the key is pressed.
+const WHEEL_SPEED: i64 = 15;
+
+#[derive(Default)]
+pub struct MouseKeys {
+    unreported_movement: Point2D<i64>,
+    // Scrolling, y is up.
+    unreported_scroll: Point2D<i64>,
+    // Updates the movement keys have been held for, zero when released.
+    move_updates: i64,
+    wheel_held: bool,
+}
+
+/// Unit vector of the held directions, opposing directions cancel out.
+fn direction_vector(directions: &[Direction]) -> Point2D<i64> {
+    directions.iter().fold(Point2D::default(), |v, d| match d {
+        Direction::Up => Point2D { x: v.x, y: v.y - 1 },
+        Direction::Down => Point2D { x: v.x, y: v.y + 1 },
+        Direction::Left => Point2D { x: v.x - 1, y: v.y },
+        Direction::Right => Point2D { x: v.x + 1, y: v.y },
+    })
+}
+
+impl MouseKeys {
+    /// Called once per mouse report with the movement and wheel keys held.
+    pub fn update(&mut self, moves: &[Direction], wheels: &[Direction]) {
+        let movement = direction_vector(moves);
+        if moves.is_empty() {
+            self.move_updates = 0;
+            self.unreported_movement = Point2D::default();
+        } else {
+            self.move_updates = (self.move_updates + 1).min(RAMP_UPDATES);
+            let speed = START_SPEED + (MAX_SPEED - START_SPEED) * self.move_updates / RAMP_UPDATES;
+            let per_update = speed * SUBPIXELS / UPDATES_PER_SECOND;
+            self.unreported_movement.x += movement.x * per_update;
+            self.unreported_movement.y += movement.y * per_update;
+        }
+
+        // The wheel counts up as positive, the opposite of the cursor's y.
+        let wheel = direction_vector(wheels);
+        if wheels.is_empty() {
+            self.wheel_held = false;
+            self.unreported_scroll = Point2D::default();
+        } else {
+            let per_update = if self.wheel_held { WHEEL_SPEED * SUBPIXELS / UPDATES_PER_SECOND } else { SUBPIXELS };
+            self.wheel_held = true;
+            self.unreported_scroll.x += wheel.x * per_update;
+            self.unreported_scroll.y -= wheel.y * per_update;
+        }
+    }
+
+    /// Scrolls by whole notches from a wheel, up positive.  Called after
+    /// `update`, which drops scrolling when no wheel keys are held.
+    pub fn turn_wheel(&mut self, detents: i32) {
+        self.unreported_scroll.y += i64::from(detents) * SUBPIXELS;
+    }
+
+    pub fn populate_report(&self, report: &mut WheelMouseReport, wheel: &WheelMultiplier) {
+        let whole = |v: i64, unit: i64| i8::try_from((v / unit).clamp(i8::MIN.into(), i8::MAX.into())).unwrap();
+        report.x = whole(self.unreported_movement.x, SUBPIXELS);
+        report.y = whole(self.unreported_movement.y, SUBPIXELS);
+        report.horizontal_wheel = whole(self.unreported_scroll.x, wheel_unit(wheel.horizontal));
+        report.vertical_wheel = whole(self.unreported_scroll.y, wheel_unit(wheel.vertical));
+    }
+
+    pub fn account_report(&mut self, report: &WheelMouseReport, wheel: &WheelMultiplier) {
+        self.unreported_movement.x -= i64::from(report.x) * SUBPIXELS;
+        self.unreported_movement.y -= i64::from(report.y) * SUBPIXELS;
+        self.unreported_scroll.x -= i64::from(report.horizontal_wheel) * wheel_unit(wheel.horizontal);
+        self.unreported_scroll.y -= i64::from(report.vertical_wheel) * wheel_unit(wheel.vertical);
+    }
+}
@@ -0,0 +1,104 @@
+// How the joystick is mounted.  Readings are swapped, inverted and then
+// rotated so pushing the stick away moves the cursor up whatever the
+// mounting.
+
+use crate::mouse::Point2D;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Orientation {
+    /// Exchange the X and Y axes.
+    pub swap_axes: bool,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    /// Clockwise rotation in degrees, for a stick mounted at an angle.
+    pub rotation: i16,
+}
+
+/// sin(degrees) in 1/1024ths, using Bhaskara's approximation which is
+/// within 0.2% and needs no floating point.
+fn sin(degrees: i32) -> i32 {
+    let degrees = degrees.rem_euclid(360);
+    let (x, sign) = if degrees < 180 { (degrees, 1) } else { (degrees - 180, -1) };
+    sign * 4 * x * (180 - x) * 1024 / (40500 - x * (180 - x))
+}
+
+fn cos(degrees: i32) -> i32 {
+    sin(degrees + 90)
+}
+
+impl Orientation {
+    pub fn apply(&self, reading: &Point2D<i16>) -> Point2D<i16> {
+        let (mut x, mut y) = (i32::from(reading.x), i32::from(reading.y));
+        if self.swap_axes {
+            (x, y) = (y, x);
+        }
+        if self.invert_x {
+            x = -x;
+        }
+        if self.invert_y {
+            y = -y;
+        }
+
+        if self.rotation.rem_euclid(360) != 0 {
+            // Screen coordinates have y down, so this turns clockwise.
+            let (s, c) = (sin(self.rotation.into()), cos(self.rotation.into()));
+            (x, y) = ((x * c - y * s) / 1024, (x * s + y * c) / 1024);
+        }
+
+        let clamp = |v: i32| i16::try_from(v.clamp(i16::MIN.into(), i16::MAX.into())).unwrap();
+        Point2D { x: clamp(x), y: clamp(y) }
+    }
+
+    /// Turn by `degrees`, keeping the angle within a circle.
+    pub fn rotate(&mut self, degrees: i16) {
+        let rotation = (i32::from(self.rotation) + i32::from(degrees)).rem_euclid(360);
+        self.rotation = i16::try_from(rotation).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotated(rotation: i16) -> Orientation {
+        Orientation { rotation, ..Default::default() }
+    }
+
+    #[test]
+    fn quarter_turns_are_exact() {
+        let reading = Point2D { x: 100, y: 30 };
+        assert_eq!(rotated(0).apply(&reading), reading);
+        assert_eq!(rotated(90).apply(&reading), Point2D { x: -30, y: 100 });
+        assert_eq!(rotated(180).apply(&reading), Point2D { x: -100, y: -30 });
+        assert_eq!(rotated(270).apply(&reading), Point2D { x: 30, y: -100 });
+        // Angles outside a circle turn the same way.
+        assert_eq!(rotated(-90).apply(&reading), rotated(270).apply(&reading));
+        assert_eq!(rotated(450).apply(&reading), rotated(90).apply(&reading));
+    }
+
+    #[test]
+    fn swaps_and_inverts_before_rotating() {
+        let reading = Point2D { x: 100, y: 30 };
+        let mounted = Orientation { swap_axes: true, invert_x: true, invert_y: false, rotation: 90 };
+        assert_eq!(mounted.apply(&reading), Point2D { x: -100, y: -30 });
+
+        // Turned far enough to leave the range of a reading.
+        let corner = Point2D { x: i16::MAX, y: i16::MIN };
+        assert_eq!(rotated(45).apply(&corner), Point2D { x: i16::MAX, y: 0 });
+    }
+
+    #[test]
+    fn rotate_stays_within_a_circle() {
+        let mut orientation = Orientation::default();
+        orientation.rotate(-90);
+        assert_eq!(orientation.rotation, 270);
+        orientation.rotate(180);
+        assert_eq!(orientation.rotation, 90);
+        orientation.rotate(i16::MAX);
+        assert_eq!(orientation.rotation, 97);
+
+        orientation.rotation = i16::MIN;
+        orientation.rotate(i16::MIN);
+        assert!((0..360).contains(&orientation.rotation));
+    }
+}
@@ -0,0 +1,147 @@
+use heapless::Deque;
+use usbd_human_interface_device::page::Keyboard;
+
+pub type KeySet = heapless::Vec<Keyboard, 32>;
+
+/// Number of key state changes we can buffer while the host isn't reading.
+const QUEUE_DEPTH: usize = 16;
+
+/// Queue of keyboard states waiting to be sent to the host.
+///
+/// Every change in the set of pressed keys is queued, so a tap which is
+/// pressed and released between two reports is still reported, and presses
+/// are delivered in the order they happened.  If the queue fills up the
+/// newest entry absorbs further presses, so a key is never dropped, only
+/// its release delayed.  Presses absorbed that way reach the host together,
+/// losing their order.
+#[derive(Default)]
+pub struct KeyReportQueue {
+    pending: Deque<KeySet, QUEUE_DEPTH>,
+    last_sent: KeySet,
+}
+
+fn same_keys(a: &[Keyboard], b: &[Keyboard]) -> bool {
+    a.len() == b.len() && a.iter().all(|k| b.contains(k))
+}
+
+impl KeyReportQueue {
+    /// Record the keys currently held, queueing a report if they changed.
+    pub fn push(&mut self, keys: &[Keyboard]) {
+        let latest = self.pending.back().unwrap_or(&self.last_sent);
+        if same_keys(latest, keys) {
+            return;
+        }
+
+        let keys = KeySet::from_slice(keys).unwrap();
+        if self.pending.is_full() {
+            let back = self.pending.back_mut().unwrap();
+            keys.iter().for_each(|k| {
+                if !back.contains(k) {
+                    // Can't overflow in practice, the matrix has fewer keys than this.
+                    let _ = back.push(*k);
+                }
+            });
+        } else {
+            self.pending.push_back(keys).unwrap();
+        }
+    }
+
+    /// Keys for the next report to send.
+    pub fn next_report(&self) -> &[Keyboard] {
+        self.pending.front().unwrap_or(&self.last_sent)
+    }
+
+    /// Whether there are reports waiting to be sent.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Called once the report from `next_report()` has been accepted by the endpoint.
+    pub fn report_sent(&mut self) {
+        if let Some(keys) = self.pending.pop_front() {
+            self.last_sent = keys;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Keyboard::{A, B, C};
+
+    /// Sends everything queued, returning the reports.
+    fn drain(queue: &mut KeyReportQueue) -> Vec<Vec<Keyboard>> {
+        let mut reports = Vec::new();
+        while queue.has_pending() {
+            reports.push(queue.next_report().to_vec());
+            queue.report_sent();
+        }
+        reports
+    }
+
+    #[test]
+    fn tap_between_reports_is_reported() {
+        let mut queue = KeyReportQueue::default();
+        queue.push(&[A]);
+        queue.push(&[]);
+        assert_eq!(drain(&mut queue), [vec![A], vec![]]);
+    }
+
+    #[test]
+    fn unchanged_keys_queue_nothing() {
+        let mut queue = KeyReportQueue::default();
+        queue.push(&[]);
+        assert!(!queue.has_pending());
+        queue.push(&[A]);
+        queue.push(&[A]);
+        assert_eq!(drain(&mut queue), [vec![A]]);
+        queue.push(&[A]);
+        assert!(!queue.has_pending());
+        assert_eq!(queue.next_report(), [A]);
+    }
+
+    #[test]
+    fn rolling_keeps_order() {
+        let mut queue = KeyReportQueue::default();
+        queue.push(&[A]);
+        queue.push(&[A, B]);
+        queue.push(&[B]);
+        queue.push(&[]);
+        assert_eq!(drain(&mut queue), [vec![A], vec![A, B], vec![B], vec![]]);
+    }
+
+    #[test]
+    fn rolling_across_sends_keeps_order() {
+        let mut queue = KeyReportQueue::default();
+        queue.push(&[A]);
+        assert_eq!(drain(&mut queue), [vec![A]]);
+        queue.push(&[A, B]);
+        queue.push(&[B]);
+        assert_eq!(drain(&mut queue), [vec![A, B], vec![B]]);
+    }
+
+    #[test]
+    fn full_queue_folds_presses_into_newest() {
+        let mut queue = KeyReportQueue::default();
+        for i in 0..QUEUE_DEPTH {
+            queue.push(if i % 2 == 0 { &[A] } else { &[] });
+        }
+        // Full, a tap of B and then one of C.
+        queue.push(&[B]);
+        queue.push(&[]);
+        queue.push(&[C]);
+        queue.push(&[]);
+
+        let reports = drain(&mut queue);
+        assert_eq!(reports.len(), QUEUE_DEPTH);
+        for (i, report) in reports[..QUEUE_DEPTH - 1].iter().enumerate() {
+            assert_eq!(*report, if i % 2 == 0 { vec![A] } else { vec![] });
+        }
+        // Neither tap is lost, but they arrive together.
+        assert_eq!(reports[QUEUE_DEPTH - 1], [B, C]);
+
+        // Their release goes once there is room.
+        queue.push(&[]);
+        assert_eq!(drain(&mut queue), [vec![]]);
+    }
+}
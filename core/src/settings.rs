@@ -0,0 +1,404 @@
+// Settings which survive a reboot, and how they are laid out in a page of
+// flash:
+//
+//   magic | version | length | fields... | checksum
+//
+// The checksum covers everything before it.  Fields are only ever added at
+// the end, settings saved before one was added load it as its default.
+// VERSION is only bumped when an existing field changes meaning.
+//
+// Each field is checked as it's loaded, one which doesn't make sense gets
+// its default or is brought into range, the others are kept.
+
+use crate::absolute::ABSOLUTE_MAX;
+use crate::acceleration::{Curve, PointerSettings, MAX_SPEED_RANGE, PRECISION_DIVISORS, SENSITIVITY_RANGE};
+use crate::auto_mouse::{AutoMouseConfig, ACTIVATION_DISTANCE_RANGE, TIMEOUT_RANGE_MS};
+use crate::calibration::{AxisCalibration, JoystickCalibration};
+use crate::deadzone::{Deadzone, DeadzoneShape, RADIUS_RANGE};
+use crate::filter::FilterKind;
+use crate::key_table::MouseButton;
+use crate::mouse::Point2D;
+use crate::orientation::Orientation;
+
+/// Marks a page of flash as holding our settings, erased flash reads as 0xff.
+const MAGIC: [u8; 4] = *b"KBST";
+/// Bump when the meaning of a field below changes, older settings are then
+/// ignored.  Not for adding fields.
+const VERSION: u8 = 11;
+/// Magic, version and length.
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+/// How many keys the keyboard reports to the host at once.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub enum RolloverMode {
+    /// Boot compatible report with an NKRO bitmap appended.
+    #[default]
+    Nkro,
+    /// Plain boot keyboard report, for BIOSes and KVMs which can't cope with NKRO.
+    Boot6kro,
+}
+
+/// Settings which survive a reboot.
+#[derive(Clone, PartialEq, Default, defmt::Format)]
+pub struct Settings {
+    pub rollover: RolloverMode,
+    pub pointer: PointerSettings,
+    pub auto_mouse: AutoMouseConfig,
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |acc, b| acc.rotate_left(1) ^ b)
+}
+
+/// Appends fields to a page of flash.
+struct Writer<'a> {
+    bytes: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn u8(&mut self, value: u8) {
+        self.bytes[self.pos] = value;
+        self.pos += 1;
+    }
+
+    fn u16(&mut self, value: u16) {
+        value.to_le_bytes().iter().for_each(|b| self.u8(*b));
+    }
+
+    fn i16(&mut self, value: i16) {
+        value.to_le_bytes().iter().for_each(|b| self.u8(*b));
+    }
+
+    fn u32(&mut self, value: u32) {
+        value.to_le_bytes().iter().for_each(|b| self.u8(*b));
+    }
+}
+
+/// Reads back fields in the order `Writer` wrote them.  Past the end of
+/// what was written every read is None.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let value = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(value)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes([self.u8()?, self.u8()?, self.u8()?, self.u8()?]))
+    }
+
+    fn axis_calibration(&mut self) -> Option<AxisCalibration> {
+        Some(AxisCalibration { min: self.i16()?, centre: self.i16()?, max: self.i16()? })
+    }
+}
+
+fn clamp<T: Ord>(value: Option<T>, range: (T, T)) -> Option<T> {
+    value.map(|v| v.clamp(range.0, range.1))
+}
+
+impl Settings {
+    /// Lays the settings out in `page`, which is left 0xff after them.
+    pub fn write_to(&self, page: &mut [u8]) {
+        page.fill(0xff);
+        let mut w = Writer { bytes: page, pos: 0 };
+        MAGIC.iter().for_each(|b| w.u8(*b));
+        w.u8(VERSION);
+        // Filled in once the fields are written.
+        w.u8(0);
+
+        w.u8(match self.rollover {
+            RolloverMode::Nkro => 0,
+            RolloverMode::Boot6kro => 1,
+        });
+        let (curve, exponent) = match self.pointer.curve {
+            Curve::Linear => (0, 0),
+            Curve::Power(exponent) => (1, exponent),
+            Curve::Table => (2, 0),
+            Curve::Adaptive => (3, 0),
+        };
+        w.u8(curve);
+        w.u8(exponent);
+        w.u16(self.pointer.sensitivity);
+        w.u16(self.pointer.max_speed);
+        w.u8(match self.pointer.deadzone.shape {
+            DeadzoneShape::Radial => 0,
+            DeadzoneShape::Axial => 1,
+        });
+        w.u16(self.pointer.deadzone.radius);
+        match &self.pointer.calibration {
+            Some(c) => {
+                w.u8(1);
+                [c.x, c.y].iter().for_each(|axis| {
+                    w.i16(axis.min);
+                    w.i16(axis.centre);
+                    w.i16(axis.max);
+                });
+            }
+            None => w.u8(0),
+        }
+        w.u8(self.pointer.precision_divisor);
+        w.u8(match self.pointer.joystick_button {
+            MouseButton::Left => 0,
+            MouseButton::Right => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Back => 3,
+            MouseButton::Forward => 4,
+        });
+        w.u8(match self.pointer.filter {
+            FilterKind::None => 0,
+            FilterKind::Ema => 1,
+            FilterKind::Median => 2,
+            FilterKind::OneEuro => 3,
+        });
+        let o = &self.pointer.orientation;
+        w.u8(u8::from(o.swap_axes) | u8::from(o.invert_x) << 1 | u8::from(o.invert_y) << 2);
+        w.i16(o.rotation);
+        w.u16(self.pointer.absolute_centre.x);
+        w.u16(self.pointer.absolute_centre.y);
+        w.u16(self.auto_mouse.activation_distance);
+        // Zero for no timeout.
+        w.u32(self.auto_mouse.timeout_ms.unwrap_or(0));
+        // New fields go here.
+
+        let len = w.pos;
+        page[HEADER_LEN - 1] = u8::try_from(len - HEADER_LEN).unwrap();
+        page[len] = checksum(&page[..len]);
+    }
+
+    /// Reads back what `write_to` laid out, None if `page` doesn't hold
+    /// settings of this version.
+    pub fn read_from(page: &[u8]) -> Option<Self> {
+        let header = page.get(..HEADER_LEN)?;
+        if header[..MAGIC.len()] != MAGIC || header[MAGIC.len()] != VERSION {
+            return None;
+        }
+        let len = HEADER_LEN + usize::from(header[HEADER_LEN - 1]);
+        if *page.get(len)? != checksum(&page[..len]) {
+            return None;
+        }
+
+        let defaults = Self::default();
+        let pointer = &defaults.pointer;
+        let mut r = Reader { bytes: &page[HEADER_LEN..len], pos: 0 };
+
+        let rollover = match r.u8() {
+            Some(0) => RolloverMode::Nkro,
+            Some(1) => RolloverMode::Boot6kro,
+            _ => defaults.rollover,
+        };
+        let curve = match (r.u8(), r.u8()) {
+            (Some(0), Some(_)) => Curve::Linear,
+            // Those `Curve::next` goes through.
+            (Some(1), Some(exponent @ 2..=3)) => Curve::Power(exponent),
+            (Some(2), Some(_)) => Curve::Table,
+            (Some(3), Some(_)) => Curve::Adaptive,
+            _ => pointer.curve,
+        };
+        let sensitivity = clamp(r.u16(), SENSITIVITY_RANGE).unwrap_or(pointer.sensitivity);
+        let max_speed = clamp(r.u16(), MAX_SPEED_RANGE).unwrap_or(pointer.max_speed);
+        let shape = match r.u8() {
+            Some(0) => DeadzoneShape::Radial,
+            Some(1) => DeadzoneShape::Axial,
+            _ => pointer.deadzone.shape,
+        };
+        let deadzone = Deadzone {
+            shape,
+            radius: clamp(r.u16(), RADIUS_RANGE).unwrap_or(pointer.deadzone.radius),
+        };
+        let calibration = match r.u8() {
+            Some(1) => {
+                let (x, y) = (r.axis_calibration(), r.axis_calibration());
+                let ordered = |a: &AxisCalibration| a.min <= a.centre && a.centre <= a.max;
+                match (x, y) {
+                    (Some(x), Some(y)) if ordered(&x) && ordered(&y) => Some(JoystickCalibration { x, y }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let precision_divisor = r.u8()
+            .filter(|d| PRECISION_DIVISORS.contains(d))
+            .unwrap_or(pointer.precision_divisor);
+        let joystick_button = match r.u8() {
+            Some(0) => MouseButton::Left,
+            Some(1) => MouseButton::Right,
+            Some(2) => MouseButton::Middle,
+            Some(3) => MouseButton::Back,
+            Some(4) => MouseButton::Forward,
+            _ => pointer.joystick_button,
+        };
+        let filter = match r.u8() {
+            Some(0) => FilterKind::None,
+            Some(1) => FilterKind::Ema,
+            Some(2) => FilterKind::Median,
+            Some(3) => FilterKind::OneEuro,
+            _ => pointer.filter,
+        };
+        let orientation = match (r.u8(), r.i16()) {
+            (Some(flags), Some(rotation)) => Orientation {
+                swap_axes: flags & 0x1 != 0,
+                invert_x: flags & 0x2 != 0,
+                invert_y: flags & 0x4 != 0,
+                rotation: rotation.rem_euclid(360),
+            },
+            _ => pointer.orientation,
+        };
+        let absolute_centre = match (r.u16(), r.u16()) {
+            (Some(x), Some(y)) => Point2D { x: x.min(ABSOLUTE_MAX), y: y.min(ABSOLUTE_MAX) },
+            _ => pointer.absolute_centre,
+        };
+        let activation_distance = clamp(r.u16(), ACTIVATION_DISTANCE_RANGE)
+            .unwrap_or(defaults.auto_mouse.activation_distance);
+        let timeout_ms = match r.u32() {
+            Some(0) => None,
+            timeout @ Some(_) => clamp(timeout, TIMEOUT_RANGE_MS),
+            None => defaults.auto_mouse.timeout_ms,
+        };
+
+        Some(Self {
+            rollover,
+            pointer: PointerSettings {
+                curve,
+                deadzone,
+                filter,
+                orientation,
+                calibration,
+                sensitivity,
+                max_speed,
+                precision_divisor,
+                joystick_button,
+                absolute_centre,
+            },
+            auto_mouse: AutoMouseConfig { activation_distance, timeout_ms },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 256;
+
+    fn changed() -> Settings {
+        let axis = AxisCalibration { min: -300, centre: 10, max: 420 };
+        Settings {
+            rollover: RolloverMode::Boot6kro,
+            pointer: PointerSettings {
+                curve: Curve::Power(3),
+                deadzone: Deadzone { shape: DeadzoneShape::Axial, radius: 20 },
+                filter: FilterKind::OneEuro,
+                orientation: Orientation { swap_axes: true, invert_x: false, invert_y: true, rotation: 270 },
+                calibration: Some(JoystickCalibration { x: axis, y: AxisCalibration { min: 0, ..axis } }),
+                sensitivity: 150,
+                max_speed: 1200,
+                precision_divisor: 16,
+                joystick_button: MouseButton::Forward,
+                absolute_centre: Point2D { x: 100, y: 30000 },
+            },
+            auto_mouse: AutoMouseConfig { activation_distance: 25, timeout_ms: None },
+        }
+    }
+
+    fn written(settings: &Settings) -> [u8; PAGE_SIZE] {
+        let mut page = [0u8; PAGE_SIZE];
+        settings.write_to(&mut page);
+        page
+    }
+
+    /// Drops the last `count` bytes of fields, as an older layout would
+    /// have been without them.
+    fn without_last_fields(page: &mut [u8], count: usize) {
+        let len = HEADER_LEN + usize::from(page[HEADER_LEN - 1]) - count;
+        page[HEADER_LEN - 1] -= u8::try_from(count).unwrap();
+        page[len] = checksum(&page[..len]);
+    }
+
+    #[test]
+    fn round_trips() {
+        for settings in [Settings::default(), changed()] {
+            assert!(Settings::read_from(&written(&settings)) == Some(settings));
+        }
+        let mut settings = changed();
+        settings.pointer.curve = Curve::Adaptive;
+        settings.pointer.calibration = None;
+        settings.auto_mouse.timeout_ms = Some(12000);
+        assert!(Settings::read_from(&written(&settings)) == Some(settings));
+    }
+
+    #[test]
+    fn rejects_other_pages() {
+        assert!(Settings::read_from(&[0xff; PAGE_SIZE]).is_none());
+        assert!(Settings::read_from(&[0; 3]).is_none());
+
+        let page = written(&changed());
+        let mut corrupt = page;
+        corrupt[HEADER_LEN + 3] ^= 0x10;
+        assert!(Settings::read_from(&corrupt).is_none());
+
+        let mut other_version = page;
+        other_version[MAGIC.len()] = VERSION - 1;
+        assert!(Settings::read_from(&other_version).is_none());
+    }
+
+    #[test]
+    fn fields_added_later_load_as_defaults() {
+        let mut page = written(&changed());
+        // Saved before the auto mouse settings were added.
+        without_last_fields(&mut page, 6);
+        let settings = Settings::read_from(&page).unwrap();
+        assert!(settings.pointer == changed().pointer);
+        assert!(settings.auto_mouse == AutoMouseConfig::default());
+
+        // And part way through a field.
+        without_last_fields(&mut page, 1);
+        let settings = Settings::read_from(&page).unwrap();
+        assert!(settings.pointer.absolute_centre == PointerSettings::default().absolute_centre);
+        assert!(settings.pointer.orientation == changed().pointer.orientation);
+    }
+
+    #[test]
+    fn nonsense_fields_are_fixed() {
+        let mut settings = changed();
+        settings.pointer.curve = Curve::Power(9);
+        settings.pointer.sensitivity = 9999;
+        settings.pointer.max_speed = 0;
+        settings.pointer.deadzone.radius = 1000;
+        settings.pointer.orientation.rotation = -90;
+        settings.pointer.precision_divisor = 3;
+        settings.pointer.absolute_centre = Point2D { x: u16::MAX, y: 5 };
+        settings.pointer.calibration.as_mut().unwrap().y.min = 500;
+        settings.auto_mouse = AutoMouseConfig { activation_distance: 0, timeout_ms: Some(100_000) };
+
+        let loaded = Settings::read_from(&written(&settings)).unwrap();
+        let defaults = PointerSettings::default();
+        assert!(loaded.pointer.curve == defaults.curve);
+        assert_eq!(loaded.pointer.sensitivity, SENSITIVITY_RANGE.1);
+        assert_eq!(loaded.pointer.max_speed, MAX_SPEED_RANGE.0);
+        assert_eq!(loaded.pointer.deadzone.radius, RADIUS_RANGE.1);
+        assert_eq!(loaded.pointer.orientation.rotation, 270);
+        assert_eq!(loaded.pointer.precision_divisor, defaults.precision_divisor);
+        assert!(loaded.pointer.absolute_centre == Point2D { x: ABSOLUTE_MAX, y: 5 });
+        assert!(loaded.pointer.calibration.is_none());
+        assert_eq!(loaded.auto_mouse.activation_distance, ACTIVATION_DISTANCE_RANGE.0);
+        assert_eq!(loaded.auto_mouse.timeout_ms, Some(TIMEOUT_RANGE_MS.1));
+        // The sensible ones are kept.
+        assert!(loaded.rollover == RolloverMode::Boot6kro);
+        assert!(loaded.pointer.filter == FilterKind::OneEuro);
+        assert!(loaded.pointer.joystick_button == MouseButton::Forward);
+    }
+}
@@ -0,0 +1,315 @@
+// The status as a picture, for an SSD1306 OLED.  On a 128x32 display:
+//
+//   [layer][mode][caps][link] 42   +--------+
+//                                  |   +    |
+//        ||   ||| ||||       |     |        |
+//      |||||  ||||||||||    ||     +--------+
+//
+// Icons along the top, words per minute for the last minute or so below,
+// and the joystick's position in a box on the right.  A 128x64 display gets
+// a taller graph and a bigger box.
+
+use crate::calibration::CALIBRATED_RANGE;
+use crate::framebuffer::{Framebuffer, WIDTH};
+use crate::mouse::Point2D;
+use crate::status_screen::{Connection, Status};
+
+/// Speed at the top of the graph.
+const GRAPH_MAX_WPM: i32 = 120;
+const ICON_SIZE: i32 = 8;
+const ICON_SPACING: i32 = 10;
+const GRAPH_TOP: i32 = 10;
+
+#[rustfmt::skip]
+const BASE_LAYER_ICON: [u8; 8] = [
+    0b01111110,
+    0b10000001,
+    0b10000001,
+    0b10000001,
+    0b10000001,
+    0b01111110,
+    0b00000000,
+    0b00000000,
+];
+#[rustfmt::skip]
+const FN_LAYER_ICON: [u8; 8] = [
+    0b11100000,
+    0b10000000,
+    0b11001110,
+    0b10001001,
+    0b10001001,
+    0b10001001,
+    0b00000000,
+    0b00000000,
+];
+#[rustfmt::skip]
+const CAPS_LOCK_ICON: [u8; 8] = [
+    0b00010000,
+    0b00111000,
+    0b01111100,
+    0b00111000,
+    0b00111000,
+    0b00000000,
+    0b00111000,
+    0b00000000,
+];
+#[rustfmt::skip]
+const MOUSE_ICON: [u8; 8] = [
+    0b00111100,
+    0b01001010,
+    0b01001010,
+    0b01111110,
+    0b01000010,
+    0b01000010,
+    0b00111100,
+    0b00000000,
+];
+#[rustfmt::skip]
+const KEYBOARD_ICON: [u8; 8] = [
+    0b00000000,
+    0b11111111,
+    0b10101011,
+    0b10000001,
+    0b11010101,
+    0b10000001,
+    0b11111111,
+    0b00000000,
+];
+#[rustfmt::skip]
+const NO_USB_ICON: [u8; 8] = [
+    0b11111111,
+    0b11000011,
+    0b10100101,
+    0b10011001,
+    0b10011001,
+    0b10100101,
+    0b11000011,
+    0b11111111,
+];
+#[rustfmt::skip]
+const ASLEEP_ICON: [u8; 8] = [
+    0b11110000,
+    0b00100000,
+    0b01000000,
+    0b11110111,
+    0b00000010,
+    0b00000100,
+    0b00000111,
+    0b00000000,
+];
+#[rustfmt::skip]
+const NO_JOYSTICK_ICON: [u8; 8] = [
+    0b00011000,
+    0b00111100,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b01111110,
+    0b01111110,
+    0b00000000,
+];
+
+/// 3x5 digits.
+#[rustfmt::skip]
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+fn draw_number(frame: &mut Framebuffer, x: i32, y: i32, number: u16) {
+    let mut digits: heapless::Vec<usize, 5> = heapless::Vec::new();
+    let mut n = usize::from(number);
+    loop {
+        digits.push(n % 10).unwrap();
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    for (i, digit) in (0..).zip(digits.iter().rev()) {
+        frame.bitmap(x + i * 4, y, &DIGITS[*digit], 3);
+    }
+}
+
+/// Draws a frame.  `history` is words per minute, oldest first, and
+/// `deflection` None when there is no joystick.
+pub fn render<'a>(
+    frame: &mut Framebuffer,
+    status: &Status,
+    deflection: Option<&Point2D<i16>>,
+    history: impl DoubleEndedIterator<Item = &'a u16>,
+) {
+    frame.clear();
+    let height = i32::try_from(frame.height()).unwrap();
+    let width = i32::try_from(WIDTH).unwrap();
+
+    let mut x = 0;
+    let mut icon = |frame: &mut Framebuffer, bitmap: &[u8; 8]| {
+        frame.bitmap(x, 0, bitmap, ICON_SIZE);
+        x += ICON_SPACING;
+    };
+    icon(frame, if status.fn_layer { &FN_LAYER_ICON } else { &BASE_LAYER_ICON });
+    icon(frame, if status.mouse_mode { &MOUSE_ICON } else { &KEYBOARD_ICON });
+    if status.caps_lock {
+        icon(frame, &CAPS_LOCK_ICON);
+    }
+    match status.connection {
+        Connection::NoUsb => icon(frame, &NO_USB_ICON),
+        Connection::Asleep => icon(frame, &ASLEEP_ICON),
+        Connection::NoJoystick => icon(frame, &NO_JOYSTICK_ICON),
+        Connection::Connected => {}
+    }
+    draw_number(frame, x + 2, 1, status.words_per_minute);
+
+    // The joystick's box is square, as tall as the display.
+    let box_left = width - height;
+    frame.rect(box_left, 0, height, height);
+    let centre = box_left + height / 2;
+    let middle = height / 2;
+    match deflection {
+        Some(deflection) => {
+            let reach = height / 2 - 3;
+            let x = centre + i32::from(deflection.x) * reach / CALIBRATED_RANGE;
+            // Down is positive, as on the screen.
+            let y = middle + i32::from(deflection.y) * reach / CALIBRATED_RANGE;
+            frame.set(centre, middle);
+            frame.fill_rect(x - 2, y, 5, 1);
+            frame.fill_rect(x, y - 2, 1, 5);
+        }
+        None => {
+            for d in -4..=4 {
+                frame.set(centre + d, middle + d);
+                frame.set(centre + d, middle - d);
+            }
+        }
+    }
+
+    // Newest speed on the right, a column each.
+    let graph_width = box_left - 2;
+    let graph_height = height - GRAPH_TOP;
+    for (x, wpm) in (0..graph_width).rev().zip(history.rev()) {
+        let bar = (i32::from(*wpm) * graph_height / GRAPH_MAX_WPM).min(graph_height);
+        frame.fill_rect(x, height - bar, 1, bar);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: Status = Status {
+        fn_layer: false,
+        caps_lock: false,
+        mouse_mode: false,
+        words_per_minute: 42,
+        connection: Connection::Connected,
+    };
+
+    fn draw(height: usize, status: &Status, deflection: Option<&Point2D<i16>>, history: &[u16]) -> Framebuffer {
+        let mut frame = Framebuffer::new(height);
+        render(&mut frame, status, deflection, history.iter());
+        frame
+    }
+
+    /// Lit pixels of the row, as a PBM does it.
+    fn row(frame: &Framebuffer, y: usize) -> String {
+        (0..WIDTH).map(|x| if frame.get(x, y) { '1' } else { '0' }).collect()
+    }
+
+    #[test]
+    fn icons_follow_status() {
+        let frame = draw(32, &STATUS, None, &[]);
+        // Base layer then keyboard, along the top.
+        assert_eq!(row(&frame, 0)[..20], *"01111110000000000000");
+        assert_eq!(row(&frame, 1)[..20], *"10000001001111111100");
+        // 42 to the right of them, 3x5 digits a pixel down.
+        assert_eq!(row(&frame, 1)[22..29], *"1010111");
+        assert_eq!(row(&frame, 3)[22..29], *"1110111");
+        assert_eq!(row(&frame, 5)[22..29], *"0010111");
+
+        let status = Status {
+            fn_layer: true,
+            caps_lock: true,
+            mouse_mode: true,
+            connection: Connection::NoUsb,
+            ..STATUS
+        };
+        let frame = draw(32, &status, None, &[]);
+        // Fn, mouse, caps lock then no USB, the number after all four.
+        assert_eq!(row(&frame, 0)[..38], *"11100000000011110000000100000011111111");
+        assert!(frame.get(42, 1) && !frame.get(43, 1) && frame.get(44, 1));
+    }
+
+    #[test]
+    fn joystick_box() {
+        let frame = draw(32, &STATUS, None, &[]);
+        // A square on the right, as tall as the display.
+        assert!((96..128).all(|x| frame.get(x, 0) && frame.get(x, 31)));
+        assert!((0..32).all(|y| frame.get(96, y) && frame.get(127, y)));
+        assert!(!frame.get(95, 16));
+        // No joystick is a cross in the middle.
+        assert!((0..=8).all(|d| frame.get(108 + d, 12 + d) && frame.get(108 + d, 20 - d)));
+
+        // Pushed fully right and a little up, a crosshair near the right
+        // edge, and a dot where the centre is.
+        let deflection = Point2D { x: 512, y: -256 };
+        let frame = draw(32, &STATUS, Some(&deflection), &[]);
+        assert!(frame.get(112, 16));
+        assert!(!frame.get(116, 20));
+        let (x, y) = (112 + 13, 16 - 6);
+        assert!((x - 2..=x + 2).all(|x| frame.get(x, y)));
+        assert!((y - 2..=y + 2).all(|y| frame.get(x, y)));
+        assert!(!frame.get(x - 1, y - 1) && !frame.get(x + 1, y + 1));
+
+        let frame = draw(64, &STATUS, Some(&deflection), &[]);
+        assert!((0..64).all(|y| frame.get(64, y) && frame.get(127, y)));
+        assert!(frame.get(96, 32));
+        assert!(frame.get(96 + 29, 32 - 14));
+    }
+
+    #[test]
+    fn history_graph() {
+        // Oldest first, the newest ends up next to the box.
+        let frame = draw(32, &STATUS, None, &[120, 0, 60, 240]);
+        let column = |x| (0..32).filter(|y| frame.get(x, *y)).count();
+        // The graph is 22 pixels tall, a bar full height at 120 wpm.
+        assert_eq!(column(93), 22);
+        assert_eq!(column(92), 11);
+        assert!(frame.get(92, 31) && frame.get(92, 21) && !frame.get(92, 20));
+        assert_eq!(column(91), 0);
+        assert_eq!(column(90), 22);
+        assert_eq!(column(89), 0);
+        assert_eq!(column(94), 0);
+
+        // More history than fits drops the oldest.
+        let history = [120; 200];
+        let frame = draw(32, &STATUS, None, &history);
+        assert!((0..94).all(|x| frame.get(x, 31)));
+    }
+
+    #[test]
+    fn pbm_matches_frame() {
+        let deflection = Point2D { x: -100, y: 300 };
+        let frame = draw(32, &STATUS, Some(&deflection), &[30, 90]);
+        let mut pbm = String::new();
+        frame.write_pbm(&mut pbm).unwrap();
+
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("128 32"));
+        let rows: Vec<&str> = lines.collect();
+        assert_eq!(rows.len(), 32);
+        for (y, line) in rows.iter().enumerate() {
+            assert_eq!(*line, row(&frame, y), "row {y}");
+        }
+    }
+}
@@ -0,0 +1,356 @@
+// The 16x2 LCD as a status screen.
+//
+//   Fn   ^Caps Mouse
+//    42 wpm  No USB
+//
+// Messages can be put over the top for a while.  The screen is checked for
+// changes every so often, and only the characters which changed are sent.
+
+use core::fmt::Write;
+use core::ops::Range;
+
+use crate::text_display::{DisplayError, TextDisplay};
+
+pub const COLUMNS: usize = 16;
+pub const ROWS: usize = 2;
+/// Time between redraws, each costs I2C frames and the LCD is slow.
+const REDRAW_INTERVAL_MS: u64 = 100;
+
+type Screen = [[u8; COLUMNS]; ROWS];
+const BLANK: Screen = [[b' '; COLUMNS]; ROWS];
+
+/// Custom character for Caps Lock, an arrow up out of a bar.
+const CAPS_LOCK_GLYPH: u8 = 0;
+#[rustfmt::skip]
+const CAPS_LOCK_BITMAP: [u8; 8] = [
+    0b00100,
+    0b01110,
+    0b11111,
+    0b01110,
+    0b01110,
+    0b00000,
+    0b01110,
+    0b00000,
+];
+
+/// What is missing, most important first.
+#[derive(Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Connection {
+    #[default]
+    NoUsb,
+    Asleep,
+    NoJoystick,
+    Connected,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Status {
+    pub fn_layer: bool,
+    pub caps_lock: bool,
+    pub mouse_mode: bool,
+    pub words_per_minute: u16,
+    pub connection: Connection,
+}
+
+impl Status {
+    fn compose(&self) -> Screen {
+        let mut screen = BLANK;
+        put(&mut screen[0], 0, if self.fn_layer { b"Fn" } else { b"Base" });
+        if self.caps_lock {
+            put(&mut screen[0], 5, &[CAPS_LOCK_GLYPH, b'C', b'a', b'p', b's']);
+        }
+        put_right(&mut screen[0], if self.mouse_mode { b"Mouse" } else { b"Keys" });
+
+        let mut speed: heapless::String<COLUMNS> = heapless::String::new();
+        let _ = write!(speed, "{:>3} wpm", self.words_per_minute.min(999));
+        put(&mut screen[1], 0, speed.as_bytes());
+        put_right(&mut screen[1], match self.connection {
+            Connection::NoUsb => b"No USB",
+            Connection::Asleep => b"Asleep",
+            Connection::NoJoystick => b"No stick",
+            Connection::Connected => b"",
+        });
+        screen
+    }
+}
+
+fn put(line: &mut [u8; COLUMNS], column: usize, text: &[u8]) {
+    let end = (column + text.len()).min(COLUMNS);
+    line[column..end].copy_from_slice(&text[..end - column]);
+}
+
+fn put_right(line: &mut [u8; COLUMNS], text: &[u8]) {
+    put(line, COLUMNS.saturating_sub(text.len()), text);
+}
+
+/// Columns from the first to the last which differ, None if none do.
+fn changed_span(shown: &[u8; COLUMNS], wanted: &[u8; COLUMNS]) -> Option<Range<usize>> {
+    let differs = |i: &usize| shown[*i] != wanted[*i];
+    let first = (0..COLUMNS).find(differs)?;
+    let last = (0..COLUMNS).rev().find(differs)?;
+    Some(first..last + 1)
+}
+
+struct Message {
+    screen: Screen,
+    until_ms: u64,
+}
+
+#[derive(Default)]
+pub struct StatusScreen {
+    // What the display shows, None if that isn't known.
+    shown: Option<Screen>,
+    message: Option<Message>,
+    next_draw_ms: u64,
+}
+
+impl StatusScreen {
+    /// Show text in place of the status for a while, replacing any message
+    /// already showing.  Text longer than a row continues on the next.
+    pub fn show_message(&mut self, now_ms: u64, text: &[u8], duration_ms: u64) {
+        let mut screen = BLANK;
+        for (line, chunk) in screen.iter_mut().zip(text.chunks(COLUMNS)) {
+            put(line, 0, chunk);
+        }
+        self.message = Some(Message { screen, until_ms: now_ms + duration_ms });
+    }
+
+    /// Forget what the display shows, so it's all drawn next time.  For
+    /// when it has been reset or plugged in.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// Bring the display up to date.
+    pub fn update(&mut self, now_ms: u64, status: &Status, display: &mut (impl TextDisplay + ?Sized)) {
+        if now_ms < self.next_draw_ms {
+            return;
+        }
+        self.next_draw_ms = now_ms + REDRAW_INTERVAL_MS;
+        if self.message.as_ref().is_some_and(|m| now_ms >= m.until_ms) {
+            self.message = None;
+        }
+        let wanted = match &self.message {
+            Some(message) => message.screen,
+            None => status.compose(),
+        };
+
+        // The display may have lost its custom characters too.
+        let shown = match self.shown.take() {
+            Some(shown) => shown,
+            None => {
+                let reset = display.define_char(CAPS_LOCK_GLYPH, &CAPS_LOCK_BITMAP)
+                    .and_then(|_| display.clear());
+                if reset.is_err() {
+                    return;
+                }
+                BLANK
+            }
+        };
+        for (row, line) in wanted.iter().enumerate() {
+            let Some(span) = changed_span(&shown[row], line) else {
+                continue;
+            };
+            let column = u8::try_from(span.start).unwrap();
+            let drawn = display.set_cursor(column, u8::try_from(row).unwrap())
+                .and_then(|_| display.write(&line[span]));
+            match drawn {
+                Ok(()) => {}
+                // Nothing was sent, it may still be busy clearing.  Rows
+                // already drawn are harmlessly drawn again.
+                Err(DisplayError::NotReady) => {
+                    self.shown = Some(shown);
+                    return;
+                }
+                // Left unknown if a write fails part way.
+                Err(_) => return,
+            }
+        }
+        self.shown = Some(wanted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Op {
+        Clear,
+        SetCursor(u8, u8),
+        Write(Vec<u8>),
+        DefineChar(u8),
+    }
+
+    /// Records what is sent to it, and fails every call while `ready` is
+    /// false.  Stops being ready on clearing if `busy_clearing` is set.
+    struct Recorder {
+        ops: Vec<Op>,
+        ready: bool,
+        busy_clearing: bool,
+    }
+
+    impl Default for Recorder {
+        fn default() -> Self {
+            Self { ops: Vec::new(), ready: true, busy_clearing: false }
+        }
+    }
+
+    impl Recorder {
+        fn record(&mut self, op: Op) -> Result<(), DisplayError> {
+            if !self.ready {
+                return Err(DisplayError::NotReady);
+            }
+            self.ops.push(op);
+            Ok(())
+        }
+
+        fn take(&mut self) -> Vec<Op> {
+            core::mem::take(&mut self.ops)
+        }
+    }
+
+    impl TextDisplay for Recorder {
+        fn clear(&mut self) -> Result<(), DisplayError> {
+            self.record(Op::Clear)?;
+            self.ready = !self.busy_clearing;
+            Ok(())
+        }
+
+        fn set_cursor(&mut self, column: u8, row: u8) -> Result<(), DisplayError> {
+            self.record(Op::SetCursor(column, row))
+        }
+
+        fn write(&mut self, text: &[u8]) -> Result<(), DisplayError> {
+            self.record(Op::Write(text.to_vec()))
+        }
+
+        fn define_char(&mut self, code: u8, _bitmap: &[u8; 8]) -> Result<(), DisplayError> {
+            self.record(Op::DefineChar(code))
+        }
+    }
+
+    fn write(text: &[u8]) -> Op {
+        Op::Write(text.to_vec())
+    }
+
+    #[test]
+    fn draws_everything_first() {
+        let mut screen = StatusScreen::default();
+        let mut display = Recorder::default();
+        screen.update(0, &Status::default(), &mut display);
+        assert_eq!(display.take(), [
+            Op::DefineChar(CAPS_LOCK_GLYPH),
+            Op::Clear,
+            Op::SetCursor(0, 0),
+            write(b"Base        Keys"),
+            // The rest of the row is blank already.
+            Op::SetCursor(2, 1),
+            write(b"0 wpm   No USB"),
+        ]);
+
+        // And again once it's been forgotten.
+        screen.invalidate();
+        screen.update(REDRAW_INTERVAL_MS, &Status::default(), &mut display);
+        assert_eq!(display.take().len(), 6);
+    }
+
+    #[test]
+    fn redraws_only_what_changed() {
+        let mut screen = StatusScreen::default();
+        let mut display = Recorder::default();
+        let mut status = Status::default();
+        screen.update(0, &status, &mut display);
+        display.take();
+
+        screen.update(REDRAW_INTERVAL_MS, &status, &mut display);
+        assert_eq!(display.take(), []);
+
+        status.caps_lock = true;
+        status.words_per_minute = 42;
+        status.connection = Connection::Asleep;
+        screen.update(2 * REDRAW_INTERVAL_MS, &status, &mut display);
+        assert_eq!(display.take(), [
+            Op::SetCursor(5, 0),
+            write(&[CAPS_LOCK_GLYPH, b'C', b'a', b'p', b's']),
+            Op::SetCursor(1, 1),
+            write(b"42 wpm   Asleep"),
+        ]);
+    }
+
+    #[test]
+    fn redraws_at_most_every_interval() {
+        let mut screen = StatusScreen::default();
+        let mut display = Recorder::default();
+        let mut status = Status::default();
+        screen.update(1000, &status, &mut display);
+        display.take();
+
+        status.fn_layer = true;
+        screen.update(1001, &status, &mut display);
+        screen.update(1000 + REDRAW_INTERVAL_MS - 1, &status, &mut display);
+        assert_eq!(display.take(), []);
+        screen.update(1000 + REDRAW_INTERVAL_MS, &status, &mut display);
+        assert_eq!(display.take(), [Op::SetCursor(0, 0), write(b"Fn  ")]);
+    }
+
+    #[test]
+    fn messages_expire() {
+        let mut screen = StatusScreen::default();
+        let mut display = Recorder::default();
+        let status = Status::default();
+        screen.update(0, &status, &mut display);
+        display.take();
+
+        screen.show_message(50, b"Sensitivity 150%", 1000);
+        screen.update(100, &status, &mut display);
+        assert_eq!(display.take(), [
+            Op::SetCursor(0, 0),
+            write(b"Sensitivity 150%"),
+            // The message covers the whole screen.
+            Op::SetCursor(2, 1),
+            write(&[b' '; 14]),
+        ]);
+
+        // Still showing until the first redraw after it expires.
+        screen.update(1000, &status, &mut display);
+        screen.update(1050, &status, &mut display);
+        assert_eq!(display.take(), []);
+        screen.update(1100, &status, &mut display);
+        assert_eq!(display.take(), [
+            Op::SetCursor(0, 0),
+            write(b"Base        Keys"),
+            Op::SetCursor(2, 1),
+            write(b"0 wpm   No USB"),
+        ]);
+    }
+
+    #[test]
+    fn retries_a_display_not_ready() {
+        let mut screen = StatusScreen::default();
+        let mut display = Recorder { ready: false, ..Default::default() };
+        screen.update(0, &Status::default(), &mut display);
+        display.ready = true;
+        screen.update(REDRAW_INTERVAL_MS, &Status::default(), &mut display);
+        assert_eq!(display.take()[..2], [Op::DefineChar(CAPS_LOCK_GLYPH), Op::Clear]);
+    }
+
+    #[test]
+    fn waits_for_clearing_without_clearing_again() {
+        let mut screen = StatusScreen::default();
+        let mut display = Recorder { busy_clearing: true, ..Default::default() };
+        screen.update(0, &Status::default(), &mut display);
+        assert_eq!(display.take(), [Op::DefineChar(CAPS_LOCK_GLYPH), Op::Clear]);
+
+        screen.update(REDRAW_INTERVAL_MS, &Status::default(), &mut display);
+        assert_eq!(display.take(), []);
+        display.ready = true;
+        screen.update(2 * REDRAW_INTERVAL_MS, &Status::default(), &mut display);
+        assert_eq!(display.take(), [
+            Op::SetCursor(0, 0),
+            write(b"Base        Keys"),
+            Op::SetCursor(2, 1),
+            write(b"0 wpm   No USB"),
+        ]);
+    }
+}
@@ -0,0 +1,47 @@
+// Character LCDs in the style of the HD44780, 16x2 here.  The board drives
+// one through a PCF8574 backpack itself, or has the gadget board do it.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum DisplayError {
+    /// Nothing answered, the display is probably unplugged.
+    Absent,
+    Bus,
+    /// Still being set up, try again later.
+    NotReady,
+}
+
+pub trait TextDisplay {
+    /// Blank the display and home the cursor.
+    fn clear(&mut self) -> Result<(), DisplayError>;
+
+    fn set_cursor(&mut self, column: u8, row: u8) -> Result<(), DisplayError>;
+
+    /// Show text at the cursor, which moves along after it.  Codes 0 to 7
+    /// are the custom characters.
+    fn write(&mut self, text: &[u8]) -> Result<(), DisplayError>;
+
+    /// Set the look of a custom character, `code` 0 to 7.  Rows top first,
+    /// five pixels each in the low bits.  Leaves the cursor home.
+    fn define_char(&mut self, code: u8, bitmap: &[u8; 8]) -> Result<(), DisplayError>;
+}
+
+/// For boards without a display.
+pub struct NoDisplay;
+
+impl TextDisplay for NoDisplay {
+    fn clear(&mut self) -> Result<(), DisplayError> {
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, _column: u8, _row: u8) -> Result<(), DisplayError> {
+        Ok(())
+    }
+
+    fn write(&mut self, _text: &[u8]) -> Result<(), DisplayError> {
+        Ok(())
+    }
+
+    fn define_char(&mut self, _code: u8, _bitmap: &[u8; 8]) -> Result<(), DisplayError> {
+        Ok(())
+    }
+}
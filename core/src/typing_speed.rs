@@ -0,0 +1,89 @@
+// Typing speed in words per minute, from key presses over the last few
+// seconds.  A word is five presses, the usual convention.
+
+/// Presses are counted in buckets this long.
+const BUCKET_MS: u64 = 1000;
+/// Buckets in the window, a short one so the speed follows bursts.
+const BUCKETS: usize = 10;
+const PRESSES_PER_WORD: u64 = 5;
+
+#[derive(Default)]
+pub struct TypingSpeed {
+    buckets: [u16; BUCKETS],
+    // Index of the bucket presses go in now, and when it started.
+    current: usize,
+    current_start_ms: u64,
+}
+
+impl TypingSpeed {
+    pub fn press(&mut self, now_ms: u64) {
+        self.advance(now_ms);
+        self.buckets[self.current] = self.buckets[self.current].saturating_add(1);
+    }
+
+    pub fn words_per_minute(&mut self, now_ms: u64) -> u16 {
+        self.advance(now_ms);
+        let presses: u64 = self.buckets.iter().map(|b| u64::from(*b)).sum();
+        let window_ms = BUCKET_MS * BUCKETS as u64;
+        u16::try_from(presses * 60_000 / window_ms / PRESSES_PER_WORD).unwrap_or(u16::MAX)
+    }
+
+    // Moves on to the bucket for `now_ms`, emptying those passed over.
+    fn advance(&mut self, now_ms: u64) {
+        let elapsed = now_ms.saturating_sub(self.current_start_ms) / BUCKET_MS;
+        if elapsed >= BUCKETS as u64 {
+            self.buckets = [0; BUCKETS];
+        } else {
+            for _ in 0..elapsed {
+                self.current = (self.current + 1) % BUCKETS;
+                self.buckets[self.current] = 0;
+            }
+        }
+        self.current_start_ms += elapsed * BUCKET_MS;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_presses_over_the_window() {
+        let mut speed = TypingSpeed::default();
+        assert_eq!(speed.words_per_minute(0), 0);
+        // A burst of 50 presses is 10 words, a minute's worth is 60.
+        for t in 0..50 {
+            speed.press(t * 10);
+        }
+        assert_eq!(speed.words_per_minute(500), 60);
+        assert_eq!(speed.words_per_minute(9999), 60);
+        // Then falls out of the window all at once.
+        assert_eq!(speed.words_per_minute(10_000), 0);
+    }
+
+    #[test]
+    fn follows_steady_typing() {
+        let mut speed = TypingSpeed::default();
+        // Five presses a second, for long enough to go round the buckets
+        // a few times.
+        for t in (0..30_000).step_by(200) {
+            speed.press(t);
+        }
+        assert_eq!(speed.words_per_minute(29_999), 60);
+        // After a pause only the presses from 24s on are in the window.
+        assert_eq!(speed.words_per_minute(33_000), 36);
+        assert_eq!(speed.words_per_minute(60_000), 0);
+        speed.press(60_500);
+        assert_eq!(speed.words_per_minute(61_000), 1);
+    }
+
+    #[test]
+    fn saturates() {
+        let mut speed = TypingSpeed::default();
+        for _ in 0..100_000 {
+            speed.press(0);
+        }
+        // More words than fit, from more presses than a bucket holds.
+        assert_eq!(speed.words_per_minute(0), u16::MAX);
+    }
+}
@@ -0,0 +1,28 @@
+// Everything on the board besides the key matrix: the joystick, displays
+// and whatever else is plugged in.  Which it is depends on the build, with
+// the joystick on the ADC there is no I2C bus for anything else.
+
+use pico_play_core::mouse::Point2D;
+use pico_play_core::status_screen::Status;
+use pico_play_core::text_display::TextDisplay;
+
+#[cfg(not(feature = "adc-joystick"))]
+pub(crate) use crate::peripherals::I2cPeripherals as Board;
+#[cfg(feature = "adc-joystick")]
+pub(crate) use crate::adc_joystick::AdcJoystick as Board;
+
+/// What the accessories' controls did since the last update.
+#[derive(Default)]
+pub(crate) struct AccessoryInput {
+    /// Scrolling, up positive.
+    pub wheel_detents: i32,
+}
+
+pub(crate) trait Accessories {
+    /// Where the status screen goes.
+    fn text_display(&mut self) -> &mut dyn TextDisplay;
+
+    /// Keep displays and lights up to date and read controls, called every
+    /// scan.  `deflection` is None when there is no joystick.
+    fn update(&mut self, now_ms: u64, status: &Status, deflection: Option<&Point2D<i16>>) -> AccessoryInput;
+}
@@ -0,0 +1,76 @@
+// A joystick wired straight to the Pico: the axes on ADC0 and ADC1 and the
+// push button, which shorts to ground, on GPIO 28.
+
+use embedded_hal::digital::InputPin;
+use embedded_hal_0_2::adc::OneShot;
+use rp_pico::hal::adc::{Adc, AdcPin};
+use rp_pico::hal::gpio::bank0::{Gpio26, Gpio27, Gpio28};
+use rp_pico::hal::gpio::{FunctionNull, FunctionSioInput, Pin, PullDown, PullNone, PullUp};
+
+use pico_play_core::mouse::Point2D;
+use pico_play_core::status_screen::Status;
+use pico_play_core::text_display::{NoDisplay, TextDisplay};
+
+use crate::accessories::{Accessories, AccessoryInput};
+use crate::pointing::{Poll, PointingSource};
+
+/// The ADC is 12 bit and the Arduino's 10, dropping the extra bits keeps
+/// speeds and deadzones the same for both.
+const ADC_SHIFT: u32 = 2;
+
+pub(crate) struct AdcJoystick {
+    adc: Adc,
+    x: AdcPin<Pin<Gpio26, FunctionNull, PullNone>>,
+    y: AdcPin<Pin<Gpio27, FunctionNull, PullNone>>,
+    button: Pin<Gpio28, FunctionSioInput, PullUp>,
+    // The I2C pins are the joystick's, so there is nothing else.
+    display: NoDisplay,
+}
+
+impl AdcJoystick {
+    pub fn new(
+        adc: Adc,
+        x: Pin<Gpio26, FunctionNull, PullDown>,
+        y: Pin<Gpio27, FunctionNull, PullDown>,
+        button: Pin<Gpio28, FunctionNull, PullDown>,
+    ) -> Self {
+        Self {
+            adc,
+            x: AdcPin::new(x.into_floating_disabled()).unwrap(),
+            y: AdcPin::new(y.into_floating_disabled()).unwrap(),
+            button: button.into_pull_up_input(),
+            display: NoDisplay,
+        }
+    }
+}
+
+impl PointingSource for AdcJoystick {
+    fn poll(&mut self, _now_ms: u64) -> Poll {
+        let x: u16 = self.adc.read(&mut self.x).unwrap();
+        let y: u16 = self.adc.read(&mut self.y).unwrap();
+        Poll {
+            reading: Some((
+                Point2D {
+                    x: i16::try_from(x >> ADC_SHIFT).unwrap(),
+                    y: i16::try_from(y >> ADC_SHIFT).unwrap(),
+                },
+                self.button.is_low().unwrap(),
+            )),
+            event: None,
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+impl Accessories for AdcJoystick {
+    fn text_display(&mut self) -> &mut dyn TextDisplay {
+        &mut self.display
+    }
+
+    fn update(&mut self, _now_ms: u64, _status: &Status, _deflection: Option<&Point2D<i16>>) -> AccessoryInput {
+        AccessoryInput::default()
+    }
+}
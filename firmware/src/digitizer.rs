@@ -0,0 +1,121 @@
+// HID pen digitizer, for absolute positioning with the joystick.
+//
+// Positions come from `absolute_position`.  Like the gamepad the
+// interface is always present and idle until absolute mode is switched on.
+
+use fugit::ExtU32;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usbd_human_interface_device::usb_class::prelude::*;
+
+#[rustfmt::skip]
+pub(crate) const DIGITIZER_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x0D,       // Usage Page (Digitizer),
+    0x09, 0x02,       // Usage (Pen),
+    0xA1, 0x01,       // Collection (Application),
+    0x09, 0x20,       //   Usage (Stylus),
+    0xA1, 0x00,       //   Collection (Physical),
+    0x09, 0x42,       //     Usage (Tip Switch),
+    0x09, 0x32,       //     Usage (In Range),
+    0x15, 0x00,       //     Logical Minimum (0),
+    0x25, 0x01,       //     Logical Maximum (1),
+    0x75, 0x01,       //     Report Size (1),
+    0x95, 0x02,       //     Report Count (2),
+    0x81, 0x02,       //     Input (Data, Variable, Absolute),
+    0x95, 0x06,       //     Report Count (6),
+    0x81, 0x03,       //     Input (Constant), padding
+    0x05, 0x01,       //     Usage Page (Generic Desktop),
+    0x09, 0x30,       //     Usage (X),
+    0x09, 0x31,       //     Usage (Y),
+    0x15, 0x00,       //     Logical Minimum (0),
+    0x26, 0xFF, 0x7F, //     Logical Maximum (32767),
+    0x35, 0x00,       //     Physical Minimum (0),
+    0x46, 0xFF, 0x7F, //     Physical Maximum (32767),
+    0x75, 0x10,       //     Report Size (16),
+    0x95, 0x02,       //     Report Count (2),
+    0x81, 0x02,       //     Input (Data, Variable, Absolute),
+    0xC0,             //   End Collection
+    0xC0,             // End Collection
+];
+
+/// Out of range is the default, the host then ignores the position.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct DigitizerReport {
+    /// Clicking, the pen is touching the screen.
+    pub tip: bool,
+    /// The pointer is being controlled by the pen.
+    pub in_range: bool,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl DigitizerReport {
+    fn to_bytes(self) -> [u8; 5] {
+        let mut bytes = [0u8; 5];
+        bytes[0] = u8::from(self.tip) | u8::from(self.in_range) << 1;
+        bytes[1..3].copy_from_slice(&self.x.to_le_bytes());
+        bytes[3..5].copy_from_slice(&self.y.to_le_bytes());
+        bytes
+    }
+}
+
+pub(crate) struct Digitizer<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutNone, ReportSingle>,
+    last_report: Option<DigitizerReport>,
+}
+
+impl<B: UsbBus> Digitizer<'_, B> {
+    pub fn write_report(&mut self, report: &DigitizerReport) -> Result<(), UsbHidError> {
+        if self.last_report == Some(*report) {
+            return Err(UsbHidError::Duplicate);
+        }
+
+        self.interface
+            .write_report(&report.to_bytes())
+            .map_err(UsbHidError::from)?;
+        self.last_report = Some(*report);
+        Ok(())
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for Digitizer<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutNone, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {
+        self.last_report = None;
+    }
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
+
+pub(crate) struct DigitizerConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutNone, ReportSingle>,
+}
+
+impl Default for DigitizerConfig<'_> {
+    fn default() -> Self {
+        Self {
+            interface: InterfaceBuilder::with_static_descriptor(DIGITIZER_REPORT_DESCRIPTOR).unwrap()
+                .description("Digitizer")
+                .in_endpoint(10.millis()).unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for DigitizerConfig<'a> {
+    type Allocated = Digitizer<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        Digitizer {
+            interface: Interface::new(usb_alloc, self.interface),
+            last_report: None,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Persistent storage in the last sector of the on-board flash.
+//
+// Erasing and programming the flash disables execute-in-place, so the code
+// which does it must run from RAM and may only call into the boot ROM.
+
+use rp_pico::hal::rom_data;
+
+const FLASH_BASE: u32 = 0x1000_0000;
+const FLASH_SIZE: u32 = 2048 * 1024;
+const SECTOR_SIZE: u32 = 4096;
+/// 64K block erase command, used by the ROM when it can.
+const BLOCK_ERASE_SIZE: u32 = 1 << 16;
+const BLOCK_ERASE_CMD: u8 = 0xd8;
+
+/// The smallest amount of flash we can program in one go.
+pub(crate) const PAGE_SIZE: usize = 256;
+
+/// Offset of the settings sector from the start of flash, must match memory.x.
+const SETTINGS_OFFSET: u32 = FLASH_SIZE - SECTOR_SIZE;
+
+struct RomFunctions {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+}
+
+pub(crate) fn read_settings_page() -> &'static [u8; PAGE_SIZE] {
+    unsafe { &*((FLASH_BASE + SETTINGS_OFFSET) as *const [u8; PAGE_SIZE]) }
+}
+
+pub(crate) fn write_settings_page(data: &[u8; PAGE_SIZE]) {
+    // Look everything up while we can still execute from flash.
+    let rom = RomFunctions {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+        flash_range_erase: rom_data::flash_range_erase::ptr(),
+        flash_range_program: rom_data::flash_range_program::ptr(),
+        flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+    };
+
+    // The second stage bootloader restores fast XIP once we are done,
+    // take a copy in RAM.
+    let mut boot2 = [0u32; 64];
+    unsafe {
+        core::ptr::copy_nonoverlapping(FLASH_BASE as *const u32, boot2.as_mut_ptr(), boot2.len());
+    }
+
+    cortex_m::interrupt::free(|_| unsafe {
+        erase_and_program(&rom, boot2.as_ptr(), SETTINGS_OFFSET, data.as_ptr(), data.len());
+    });
+}
+
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+unsafe fn erase_and_program(rom: &RomFunctions, boot2: *const u32, offset: u32, data: *const u8, len: usize) {
+    unsafe {
+        (rom.connect_internal_flash)();
+        (rom.flash_exit_xip)();
+        (rom.flash_range_erase)(offset, SECTOR_SIZE as usize, BLOCK_ERASE_SIZE, BLOCK_ERASE_CMD);
+        (rom.flash_range_program)(offset, data, len);
+        (rom.flash_flush_cache)();
+
+        // Thumb bit set, boot2 returns to us once XIP is configured.
+        let boot2: unsafe extern "C" fn() = core::mem::transmute((boot2 as usize) | 1);
+        boot2();
+    }
+}
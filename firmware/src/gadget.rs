@@ -0,0 +1,170 @@
+// The Arduino gadget board on the I2C bus, with the joystick and LCD.
+// Frames are described in `gadget_protocol`.
+
+use defmt::warn;
+use embedded_hal::i2c::{Error, ErrorKind};
+
+use pico_play_core::gadget_protocol;
+use pico_play_core::gadget_protocol::JOYSTICK_FRAME_LEN;
+use pico_play_core::gadget_protocol::MAX_FRAME_LEN;
+use pico_play_core::gadget_protocol::Message;
+use pico_play_core::mouse::ButtonState;
+use pico_play_core::mouse::Point2D;
+
+use crate::pointing::LinkEvent;
+
+/// Where the sketch puts the board.
+pub(crate) const GADGET_ADDRESS: u8 = 0x08;
+/// Failed reads in a row before the board is taken to be unplugged, so a
+/// single glitch doesn't drop it.
+const FAILURES_BEFORE_ABSENT: u8 = 3;
+/// While the board is absent it is polled this often, doubling after each
+/// miss up to the maximum.
+const RETRY_MIN_MS: u64 = 20;
+const RETRY_MAX_MS: u64 = 2000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub(crate) enum LinkError {
+    /// Nothing answered, the board is probably unplugged.
+    Absent,
+    /// The bus misbehaved and may need recovering.
+    Bus,
+    /// An answer, but corrupt, repeated or not a joystick sample.
+    Frame,
+}
+
+pub(crate) struct Gadget {
+    address: u8,
+    // Sequence number for the next frame we send.
+    sequence: u8,
+    // Sequence number of the last joystick sample, to spot stale reads.
+    last_sample: Option<u8>,
+    connected: bool,
+    // Failed reads since the last good one.
+    failures: u8,
+    // While absent, how long to wait after the next miss, and when to try.
+    retry_interval_ms: u64,
+    retry_at_ms: u64,
+}
+
+fn classify(e: impl Error) -> LinkError {
+    match e.kind() {
+        ErrorKind::NoAcknowledge(_) => LinkError::Absent,
+        _ => LinkError::Bus,
+    }
+}
+
+impl Gadget {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            sequence: 0,
+            last_sample: None,
+            connected: false,
+            failures: 0,
+            retry_interval_ms: 0,
+            retry_at_ms: 0,
+        }
+    }
+
+    fn send(&mut self, i2c: &mut impl embedded_hal::i2c::I2c, message: &Message) -> Result<(), LinkError> {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        let Ok(len) = gadget_protocol::encode(self.sequence, message, &mut frame) else {
+            warn!("Message too long for a frame");
+            return Err(LinkError::Frame);
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        i2c.write(self.address, &frame[..len]).map_err(classify)
+    }
+
+    /// Whether the board is plugged in and answering.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Whether to poll the board now.  Always while it is connected, with
+    /// backoff while it is not.
+    pub fn poll_due(&self, now_ms: u64) -> bool {
+        self.connected || now_ms >= self.retry_at_ms
+    }
+
+    /// Notes how a poll went, returning a change in presence.
+    pub fn record_poll<T>(&mut self, now_ms: u64, result: &Result<T, LinkError>) -> Option<LinkEvent> {
+        if result.is_ok() {
+            self.failures = 0;
+            self.retry_interval_ms = RETRY_MIN_MS;
+            if !self.connected {
+                self.connected = true;
+                return Some(LinkEvent::Connected);
+            }
+            return None;
+        }
+
+        self.failures = self.failures.saturating_add(1);
+        if self.connected {
+            if self.failures < FAILURES_BEFORE_ABSENT {
+                return None;
+            }
+            self.connected = false;
+            self.last_sample = None;
+            self.retry_interval_ms = RETRY_MIN_MS;
+            self.retry_at_ms = now_ms + self.retry_interval_ms;
+            return Some(LinkEvent::Disconnected);
+        }
+        self.retry_interval_ms = (self.retry_interval_ms * 2).clamp(RETRY_MIN_MS, RETRY_MAX_MS);
+        self.retry_at_ms = now_ms + self.retry_interval_ms;
+        None
+    }
+
+    /// Latest joystick reading.
+    pub fn read_joystick(&mut self, i2c: &mut impl embedded_hal::i2c::I2c) -> Result<(Point2D<i16>, ButtonState), LinkError> {
+        let mut frame = [0u8; JOYSTICK_FRAME_LEN];
+        i2c.read(self.address, &mut frame).map_err(classify)?;
+
+        match gadget_protocol::decode(&frame) {
+            Ok(f) => match f.message {
+                Message::JoystickSample { x, y, pressed } => {
+                    if self.last_sample == Some(f.sequence) {
+                        warn!("Repeated joystick sample {}", f.sequence);
+                        return Err(LinkError::Frame);
+                    }
+                    self.last_sample = Some(f.sequence);
+                    // The stick is mounted turned, the gadget's Y is our X.
+                    let axis = |v: u16| i16::try_from(v).map_err(|_| LinkError::Frame);
+                    Ok((Point2D { x: -axis(y)?, y: axis(x)? }, pressed))
+                }
+                other => {
+                    warn!("Expected a joystick sample, got {}", other);
+                    Err(LinkError::Frame)
+                }
+            },
+            Err(e) => {
+                warn!("Bad frame from gadget {}", e);
+                Err(LinkError::Frame)
+            }
+        }
+    }
+
+    /// Asks the board which protocol version it speaks.
+    pub fn query_version(&mut self, i2c: &mut impl embedded_hal::i2c::I2c) -> Option<u8> {
+        self.send(i2c, &Message::Ping).ok()?;
+        let mut frame = [0u8; JOYSTICK_FRAME_LEN];
+        i2c.read(self.address, &mut frame).ok()?;
+        match gadget_protocol::decode(&frame) {
+            Ok(f) => match f.message {
+                Message::Version { protocol } => Some(protocol),
+                _ => None,
+            },
+            Err(_) => None,
+        }
+    }
+
+    /// Send a message for the LCD, failing without trying while the board
+    /// is absent.
+    pub fn lcd_send(&mut self, i2c: &mut impl embedded_hal::i2c::I2c, message: &Message) -> Result<(), LinkError> {
+        if !self.connected {
+            return Err(LinkError::Absent);
+        }
+        self.send(i2c, message)
+    }
+}
@@ -0,0 +1,67 @@
+// The Arduino gadget board, as a joystick and as a display.
+
+use defmt::warn;
+
+use pico_play_core::gadget_protocol::{Message, MAX_PAYLOAD_LEN};
+use pico_play_core::text_display::{DisplayError, TextDisplay};
+
+use crate::gadget::{Gadget, LinkError};
+use crate::i2c_bus::SharedBus;
+use crate::pointing::{Poll, PointingSource};
+
+pub(crate) struct GadgetBoard {
+    bus: &'static SharedBus,
+    gadget: Gadget,
+}
+
+impl GadgetBoard {
+    pub fn new(bus: &'static SharedBus, address: u8) -> Self {
+        Self { bus, gadget: Gadget::new(address) }
+    }
+
+    fn lcd_send(&mut self, message: &Message) -> Result<(), DisplayError> {
+        self.gadget.lcd_send(&mut self.bus.device(), message).map_err(|e| match e {
+            LinkError::Absent => DisplayError::Absent,
+            LinkError::Bus | LinkError::Frame => DisplayError::Bus,
+        })
+    }
+}
+
+impl PointingSource for GadgetBoard {
+    fn poll(&mut self, now_ms: u64) -> Poll {
+        if !self.gadget.poll_due(now_ms) {
+            return Poll::default();
+        }
+
+        let reading = self.gadget.read_joystick(&mut self.bus.device());
+        if let Err(LinkError::Bus) = reading {
+            warn!("I2C bus error, recovering");
+            self.bus.recover();
+        }
+        let event = self.gadget.record_poll(now_ms, &reading);
+        Poll { reading: reading.ok(), event }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.gadget.is_connected()
+    }
+}
+
+/// The LCD wired to the Arduino.
+impl TextDisplay for GadgetBoard {
+    fn clear(&mut self) -> Result<(), DisplayError> {
+        self.lcd_send(&Message::LcdClear)
+    }
+
+    fn set_cursor(&mut self, column: u8, row: u8) -> Result<(), DisplayError> {
+        self.lcd_send(&Message::LcdCursor { column, row })
+    }
+
+    fn write(&mut self, text: &[u8]) -> Result<(), DisplayError> {
+        text.chunks(MAX_PAYLOAD_LEN).try_for_each(|chunk| self.lcd_send(&Message::LcdText(chunk)))
+    }
+
+    fn define_char(&mut self, code: u8, bitmap: &[u8; 8]) -> Result<(), DisplayError> {
+        self.lcd_send(&Message::LcdDefineChar { code, bitmap: *bitmap })
+    }
+}
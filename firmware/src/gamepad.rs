@@ -0,0 +1,135 @@
+// HID gamepad device, reporting the joystick as an absolute analog stick.
+//
+// The interface is always present, it only sends reports while gamepad
+// mode is switched on.
+
+use fugit::ExtU32;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usbd_human_interface_device::usb_class::prelude::*;
+
+use pico_play_core::calibration::CALIBRATED_RANGE;
+use pico_play_core::mouse::Point2D;
+
+/// Buttons are numbered from 1 like HID usages, up to this many.
+pub(crate) const GAMEPAD_BUTTONS: u8 = 16;
+
+#[rustfmt::skip]
+pub(crate) const GAMEPAD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop),
+    0x09, 0x05,       // Usage (Game Pad),
+    0xA1, 0x01,       // Collection (Application),
+    0x05, 0x09,       //   Usage Page (Button),
+    0x19, 0x01,       //   Usage Minimum (1),
+    0x29, 0x10,       //   Usage Maximum (16),
+    0x15, 0x00,       //   Logical Minimum (0),
+    0x25, 0x01,       //   Logical Maximum (1),
+    0x75, 0x01,       //   Report Size (1),
+    0x95, 0x10,       //   Report Count (16),
+    0x81, 0x02,       //   Input (Data, Variable, Absolute),
+    0x05, 0x01,       //   Usage Page (Generic Desktop),
+    0x09, 0x30,       //   Usage (X),
+    0x09, 0x31,       //   Usage (Y),
+    0x16, 0x01, 0x80, //   Logical Minimum (-32767),
+    0x26, 0xFF, 0x7F, //   Logical Maximum (32767),
+    0x75, 0x10,       //   Report Size (16),
+    0x95, 0x02,       //   Report Count (2),
+    0x81, 0x02,       //   Input (Data, Variable, Absolute),
+    0xC0,             // End Collection
+];
+
+/// Stick centred and no buttons pressed is the default.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct GamepadReport {
+    /// Bit n-1 is button n.
+    pub buttons: u16,
+    pub x: i16,
+    pub y: i16,
+}
+
+impl GamepadReport {
+    /// Scale a joystick deflection, in calibrated counts, to the full axis range.
+    pub fn set_stick(&mut self, deflection: &Point2D<i16>) {
+        let axis = |d: i16| {
+            let scaled = i32::from(d) * i32::from(i16::MAX) / CALIBRATED_RANGE;
+            i16::try_from(scaled.clamp(-i32::from(i16::MAX), i32::from(i16::MAX))).unwrap()
+        };
+        self.x = axis(deflection.x);
+        self.y = axis(deflection.y);
+    }
+
+    pub fn press(&mut self, button: u8) {
+        if (1..=GAMEPAD_BUTTONS).contains(&button) {
+            self.buttons |= 1 << (button - 1);
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 6] {
+        let mut bytes = [0u8; 6];
+        bytes[0..2].copy_from_slice(&self.buttons.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.x.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.y.to_le_bytes());
+        bytes
+    }
+}
+
+pub(crate) struct Gamepad<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutNone, ReportSingle>,
+    last_report: Option<GamepadReport>,
+}
+
+impl<B: UsbBus> Gamepad<'_, B> {
+    pub fn write_report(&mut self, report: &GamepadReport) -> Result<(), UsbHidError> {
+        if self.last_report == Some(*report) {
+            return Err(UsbHidError::Duplicate);
+        }
+
+        self.interface
+            .write_report(&report.to_bytes())
+            .map_err(UsbHidError::from)?;
+        self.last_report = Some(*report);
+        Ok(())
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for Gamepad<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutNone, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {
+        self.last_report = None;
+    }
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
+
+pub(crate) struct GamepadConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutNone, ReportSingle>,
+}
+
+impl Default for GamepadConfig<'_> {
+    fn default() -> Self {
+        Self {
+            interface: InterfaceBuilder::with_static_descriptor(GAMEPAD_REPORT_DESCRIPTOR).unwrap()
+                .description("Gamepad")
+                .in_endpoint(10.millis()).unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for GamepadConfig<'a> {
+    type Allocated = Gamepad<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        Gamepad {
+            interface: Interface::new(usb_alloc, self.interface),
+            last_report: None,
+        }
+    }
+}
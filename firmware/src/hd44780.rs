@@ -0,0 +1,181 @@
+// HD44780 LCD driven through a PCF8574 I2C backpack, the common blue board
+// soldered on the back of 16x2 displays.
+//
+// The PCF8574's outputs are wired
+//
+//   P0 RS, P1 RW, P2 E, P3 backlight, P4..P7 D4..D7
+//
+// so the LCD runs in 4 bit mode, each byte sent as two nibbles strobed by E.
+// It is set up a step at a time by `poll`, so waiting for the LCD doesn't
+// hold up the scan loop, and again after anything fails in case it was
+// unplugged.  Until then, and while it clears, drawing fails with
+// `NotReady`.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{Error, ErrorKind, I2c};
+
+use pico_play_core::text_display::{DisplayError, TextDisplay};
+
+/// The usual addresses, with none of A0..A2 bridged, for the PCF8574 and
+/// the PCF8574A.
+pub(crate) const BACKPACK_ADDRESSES: [u8; 2] = [0x27, 0x3f];
+
+const RS: u8 = 0x01;
+const ENABLE: u8 = 0x04;
+const BACKLIGHT: u8 = 0x08;
+
+const CLEAR: u8 = 0x01;
+const ENTRY_MODE_INCREMENT: u8 = 0x06;
+const DISPLAY_ON: u8 = 0x0c;
+const FUNCTION_4BIT_2LINE: u8 = 0x28;
+const SET_CGRAM_ADDRESS: u8 = 0x40;
+const SET_DDRAM_ADDRESS: u8 = 0x80;
+/// Start of each row in display memory.
+const ROW_OFFSETS: [u8; 2] = [0x00, 0x40];
+
+/// Waits for the LCD, a millisecond over what it needs as `now_ms` only
+/// counts whole milliseconds.  Most commands take 37us, less than sending
+/// a byte at 100kHz, but clearing takes 2ms.
+const POWER_ON_MS: u64 = 41;
+const FIRST_FUNCTION_SET_MS: u64 = 6;
+const CLEAR_MS: u64 = 3;
+/// How often to look for the backpack again after a failure.
+const RETRY_INTERVAL_MS: u64 = 1000;
+
+/// How far setting the LCD up has got.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Setup {
+    /// Not set up, or something failed.  Tried again from `retry_ms`.
+    Down { retry_ms: u64 },
+    /// Waiting until `at_ms` for the LCD before the next step.
+    PoweringOn { at_ms: u64 },
+    EnteringFourBitMode { at_ms: u64 },
+    /// Busy clearing, during setup or after `clear`.
+    Clearing { at_ms: u64 },
+    Ready,
+}
+
+fn classify(e: impl Error) -> DisplayError {
+    match e.kind() {
+        ErrorKind::NoAcknowledge(_) => DisplayError::Absent,
+        _ => DisplayError::Bus,
+    }
+}
+
+pub(crate) struct Hd44780<I, D> {
+    i2c: I,
+    delay: D,
+    address: u8,
+    setup: Setup,
+    // Latest time passed to `poll`.
+    now_ms: u64,
+}
+
+impl<I: I2c, D: DelayNs> Hd44780<I, D> {
+    pub fn new(i2c: I, delay: D, address: u8) -> Self {
+        Self { i2c, delay, address, setup: Setup::Down { retry_ms: 0 }, now_ms: 0 }
+    }
+
+    /// Takes the next step in setting the LCD up, when it's due.
+    pub fn poll(&mut self, now_ms: u64) {
+        self.now_ms = now_ms;
+        let due = match self.setup {
+            Setup::Down { retry_ms: at_ms }
+            | Setup::PoweringOn { at_ms }
+            | Setup::EnteringFourBitMode { at_ms }
+            | Setup::Clearing { at_ms } => now_ms >= at_ms,
+            Setup::Ready => false,
+        };
+        if due {
+            self.setup = self.setup_step().unwrap_or(Setup::Down { retry_ms: now_ms + RETRY_INTERVAL_MS });
+        }
+    }
+
+    // Puts the LCD into 4 bit mode, blank with the backlight on.
+    fn setup_step(&mut self) -> Result<Setup, DisplayError> {
+        let now_ms = self.now_ms;
+        Ok(match self.setup {
+            Setup::Down { .. } => {
+                // Checked first so looking for an absent backpack is quick.
+                self.i2c.write(self.address, &[BACKLIGHT]).map_err(classify)?;
+                // The LCD needs 40ms from power on, then may be in 8 bit
+                // mode or part way through a 4 bit byte.  Three 8 bit
+                // function sets get it to 8 bit mode whichever, then it can
+                // be switched to 4.
+                Setup::PoweringOn { at_ms: now_ms + POWER_ON_MS }
+            }
+            Setup::PoweringOn { .. } => {
+                self.send_nibble(0x30, 0)?;
+                Setup::EnteringFourBitMode { at_ms: now_ms + FIRST_FUNCTION_SET_MS }
+            }
+            Setup::EnteringFourBitMode { .. } => {
+                for _ in 0..2 {
+                    self.send_nibble(0x30, 0)?;
+                    self.delay.delay_us(150);
+                }
+                self.send_nibble(0x20, 0)?;
+                self.command(FUNCTION_4BIT_2LINE)?;
+                self.command(DISPLAY_ON)?;
+                self.command(ENTRY_MODE_INCREMENT)?;
+                self.command(CLEAR)?;
+                Setup::Clearing { at_ms: now_ms + CLEAR_MS }
+            }
+            Setup::Clearing { .. } | Setup::Ready => Setup::Ready,
+        })
+    }
+
+    /// Runs `f` if the LCD is set up.
+    fn with_ready<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, DisplayError>) -> Result<T, DisplayError> {
+        if self.setup != Setup::Ready {
+            return Err(DisplayError::NotReady);
+        }
+        f(self).inspect_err(|_| self.setup = Setup::Down { retry_ms: self.now_ms + RETRY_INTERVAL_MS })
+    }
+
+    // The high nibble of `nibble`, with E pulsed to latch it.
+    fn send_nibble(&mut self, nibble: u8, rs: u8) -> Result<(), DisplayError> {
+        let bits = nibble & 0xf0 | BACKLIGHT | rs;
+        self.i2c.write(self.address, &[bits | ENABLE, bits]).map_err(classify)
+    }
+
+    // Both nibbles in one I2C write, E is high for well over the 450ns
+    // needed while a byte is on the bus.
+    fn send_byte(&mut self, byte: u8, rs: u8) -> Result<(), DisplayError> {
+        let high = byte & 0xf0 | BACKLIGHT | rs;
+        let low = byte << 4 | BACKLIGHT | rs;
+        self.i2c
+            .write(self.address, &[high | ENABLE, high, low | ENABLE, low])
+            .map_err(classify)
+    }
+
+    fn command(&mut self, command: u8) -> Result<(), DisplayError> {
+        self.send_byte(command, 0)
+    }
+}
+
+impl<I: I2c, D: DelayNs> TextDisplay for Hd44780<I, D> {
+    fn clear(&mut self) -> Result<(), DisplayError> {
+        self.with_ready(|lcd| lcd.command(CLEAR))?;
+        // `now_ms` is from the last poll, which may have been a scan ago.
+        self.setup = Setup::Clearing { at_ms: self.now_ms + CLEAR_MS + 1 };
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, column: u8, row: u8) -> Result<(), DisplayError> {
+        let offset = ROW_OFFSETS[usize::from(row).min(ROW_OFFSETS.len() - 1)];
+        self.with_ready(|lcd| lcd.command(SET_DDRAM_ADDRESS | (offset + column)))
+    }
+
+    fn write(&mut self, text: &[u8]) -> Result<(), DisplayError> {
+        self.with_ready(|lcd| text.iter().try_for_each(|c| lcd.send_byte(*c, RS)))
+    }
+
+    fn define_char(&mut self, code: u8, bitmap: &[u8; 8]) -> Result<(), DisplayError> {
+        self.with_ready(|lcd| {
+            lcd.command(SET_CGRAM_ADDRESS | (code & 0x07) << 3)?;
+            bitmap.iter().try_for_each(|row| lcd.send_byte(row & 0x1f, RS))?;
+            // Writes go to character memory until display memory is addressed.
+            lcd.command(SET_DDRAM_ADDRESS)
+        })
+    }
+}
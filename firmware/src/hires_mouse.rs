@@ -0,0 +1,168 @@
+// Wheel mouse with high resolution scrolling.
+//
+// Like WheelMouse from usbd-human-interface-device, but the wheel and pan
+// each have a Resolution Multiplier feature.  Hosts which understand it
+// (Linux, Windows) set the multiplier and then treat each wheel count as a
+// fraction of a detent.  Hosts which don't leave it at one, and scrolling
+// is in whole detents as before.
+
+use fugit::ExtU32;
+use packed_struct::PackedStruct;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+use pico_play_core::mouse::WheelMultiplier;
+
+/// Wheel counts per detent once the host has enabled high resolution.
+pub(crate) const HIRES_WHEEL_MULTIPLIER: u8 = 16;
+
+/// Length of the boot mouse report: buttons, x and y.
+const BOOT_REPORT_LEN: usize = 3;
+
+// The input report is laid out like WheelMouseReport.  The feature report
+// is one byte, two bits of multiplier for each of the wheel and pan.
+#[rustfmt::skip]
+pub(crate) const HIRES_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,        // Usage Page (Generic Desktop),
+    0x09, 0x02,        // Usage (Mouse),
+    0xA1, 0x01,        // Collection (Application),
+    0x09, 0x01,        //   Usage (Pointer),
+    0xA1, 0x00,        //   Collection (Physical),
+    0x95, 0x08,        //     Report Count (8),
+    0x75, 0x01,        //     Report Size (1),
+    0x05, 0x09,        //     Usage Page (Buttons),
+    0x19, 0x01,        //     Usage Minimum (1),
+    0x29, 0x08,        //     Usage Maximum (8),
+    0x15, 0x00,        //     Logical Minimum (0),
+    0x25, 0x01,        //     Logical Maximum (1),
+    0x81, 0x02,        //     Input (Data, Variable, Absolute),
+
+    0x75, 0x08,        //     Report Size (8),
+    0x95, 0x02,        //     Report Count (2),
+    0x05, 0x01,        //     Usage Page (Generic Desktop),
+    0x09, 0x30,        //     Usage (X),
+    0x09, 0x31,        //     Usage (Y),
+    0x15, 0x81,        //     Logical Minimum (-127),
+    0x25, 0x7F,        //     Logical Maximum (127),
+    0x81, 0x06,        //     Input (Data, Variable, Relative),
+
+    0xA1, 0x02,        //     Collection (Logical),
+    0x09, 0x48,        //       Usage (Resolution Multiplier),
+    0x15, 0x00,        //       Logical Minimum (0),
+    0x25, 0x01,        //       Logical Maximum (1),
+    0x35, 0x01,        //       Physical Minimum (1),
+    0x45, 0x10,        //       Physical Maximum (16),
+    0x75, 0x02,        //       Report Size (2),
+    0x95, 0x01,        //       Report Count (1),
+    0xB1, 0x02,        //       Feature (Data, Variable, Absolute),
+    0x35, 0x00,        //       Physical Minimum (0),
+    0x45, 0x00,        //       Physical Maximum (0),
+    0x09, 0x38,        //       Usage (Wheel),
+    0x15, 0x81,        //       Logical Minimum (-127),
+    0x25, 0x7F,        //       Logical Maximum (127),
+    0x75, 0x08,        //       Report Size (8),
+    0x81, 0x06,        //       Input (Data, Variable, Relative),
+    0xC0,              //     End Collection,
+
+    0xA1, 0x02,        //     Collection (Logical),
+    0x09, 0x48,        //       Usage (Resolution Multiplier),
+    0x15, 0x00,        //       Logical Minimum (0),
+    0x25, 0x01,        //       Logical Maximum (1),
+    0x35, 0x01,        //       Physical Minimum (1),
+    0x45, 0x10,        //       Physical Maximum (16),
+    0x75, 0x02,        //       Report Size (2),
+    0xB1, 0x02,        //       Feature (Data, Variable, Absolute),
+    0x75, 0x04,        //       Report Size (4),
+    0xB1, 0x03,        //       Feature (Constant), padding
+    0x35, 0x00,        //       Physical Minimum (0),
+    0x45, 0x00,        //       Physical Maximum (0),
+    0x05, 0x0C,        //       Usage Page (Consumer),
+    0x0A, 0x38, 0x02,  //       Usage (AC Pan),
+    0x15, 0x81,        //       Logical Minimum (-127),
+    0x25, 0x7F,        //       Logical Maximum (127),
+    0x75, 0x08,        //       Report Size (8),
+    0x81, 0x06,        //       Input (Data, Variable, Relative),
+    0xC0,              //     End Collection,
+    0xC0,              //   End Collection,
+    0xC0,              // End Collection
+];
+
+pub(crate) struct HiResMouse<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutBytes8, ReportSingle>,
+    multiplier: WheelMultiplier,
+}
+
+impl<B: UsbBus> HiResMouse<'_, B> {
+    pub fn write_report(&mut self, report: &WheelMouseReport) -> Result<(), UsbHidError> {
+        let data = report.pack().map_err(|_| UsbHidError::SerializationError)?;
+        let len = if self.interface.protocol() == HidProtocol::Boot { BOOT_REPORT_LEN } else { data.len() };
+        self.interface
+            .write_report(&data[..len])
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+
+    pub fn wheel_multiplier(&self) -> WheelMultiplier {
+        self.multiplier
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for HiResMouse<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutBytes8, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {
+        self.multiplier = WheelMultiplier::default();
+    }
+
+    // There are no output reports, so anything the host sets is the
+    // Resolution Multiplier feature report.
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        let data = &mut [0u8; 8];
+        match self.interface.read_report(data) {
+            Ok(_) => {
+                let multiplier = |bits: u8| if bits & 0x3 != 0 { HIRES_WHEEL_MULTIPLIER } else { 1 };
+                self.multiplier = WheelMultiplier {
+                    vertical: multiplier(data[0]),
+                    horizontal: multiplier(data[0] >> 2),
+                };
+                defmt::info!("Wheel multiplier {}", self.multiplier);
+                Ok(())
+            }
+            Err(usb_device::UsbError::WouldBlock) => Ok(()),
+            Err(e) => Err(UsbHidError::from(e)),
+        }
+    }
+}
+
+pub(crate) struct HiResMouseConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutBytes8, ReportSingle>,
+}
+
+impl Default for HiResMouseConfig<'_> {
+    fn default() -> Self {
+        Self {
+            interface: InterfaceBuilder::with_static_descriptor(HIRES_MOUSE_REPORT_DESCRIPTOR).unwrap()
+                .boot_device(InterfaceProtocol::Mouse)
+                .description("Wheel Mouse")
+                .in_endpoint(10.millis()).unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for HiResMouseConfig<'a> {
+    type Allocated = HiResMouse<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        HiResMouse {
+            interface: Interface::new(usb_alloc, self.interface),
+            multiplier: WheelMultiplier::default(),
+        }
+    }
+}
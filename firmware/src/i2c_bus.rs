@@ -0,0 +1,173 @@
+// The I2C bus to the gadget board and LCD, and getting it going again when
+// a device is left holding SDA low.
+//
+// That happens when the gadget resets or is unplugged part way through
+// sending a byte: it waits for clocks which never come.  Clocking SCL
+// until it lets go, then sending a STOP, frees it.
+
+use core::cell::{Cell, RefCell};
+
+use embedded_hal::delay::DelayNs;
+use defmt::warn;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use fugit::HertzU32;
+use rp_pico::hal::gpio::bank0::{Gpio26, Gpio27};
+use rp_pico::hal::gpio::{AnyPin, FunctionI2C, InOutPin, Pin, PullUp};
+use rp_pico::hal::i2c::I2C;
+use rp_pico::hal::pac;
+use rp_pico::hal::Timer;
+
+pub(crate) type SdaPin = Pin<Gpio26, FunctionI2C, PullUp>;
+pub(crate) type SclPin = Pin<Gpio27, FunctionI2C, PullUp>;
+pub(crate) type I2cBus = I2C<pac::I2C1, (SdaPin, SclPin)>;
+
+/// What every device can manage, the PCF8574s go no faster.
+const STANDARD_MODE: HertzU32 = HertzU32::kHz(100);
+pub(crate) const FAST_MODE: HertzU32 = HertzU32::kHz(400);
+/// Half a clock period in standard mode, recovery goes no faster.
+const HALF_CLOCK_US: u32 = 5;
+/// A device can be at most this many clocks from the end of a byte and
+/// its acknowledge.
+const RECOVERY_CLOCKS: usize = 9;
+/// How long a device may stretch a clock during recovery.  The gadget's
+/// Arduino holds SCL low while its receive handler runs.
+const STRETCH_LIMIT_US: u32 = 1000;
+
+fn new(
+    block: pac::I2C1,
+    sda: SdaPin,
+    scl: SclPin,
+    frequency: HertzU32,
+    resets: &mut pac::RESETS,
+    system_clock: HertzU32,
+) -> I2cBus {
+    I2C::i2c1(block, sda, scl, frequency, resets, system_clock)
+}
+
+/// Lets SCL go and waits for it to rise, which a device can hold off by
+/// stretching the clock.  False if it is still low after `STRETCH_LIMIT_US`.
+fn release_clock<P: AnyPin>(scl: &mut InOutPin<P>, delay: &mut impl DelayNs) -> bool {
+    scl.set_high().unwrap();
+    for _ in 0..STRETCH_LIMIT_US {
+        if scl.is_high().unwrap() {
+            return true;
+        }
+        delay.delay_us(1);
+    }
+    scl.is_high().unwrap()
+}
+
+/// Frees a stuck bus by hand and sets the controller up again.
+///
+/// The lines are only ever driven low or let go to be pulled up, as the
+/// controller does, so nothing is shorted if a device holds one low.
+fn recover(
+    i2c: I2cBus,
+    frequency: HertzU32,
+    resets: &mut pac::RESETS,
+    system_clock: HertzU32,
+    delay: &mut impl DelayNs,
+) -> I2cBus {
+    let (block, (sda, scl)) = i2c.free(resets);
+    let mut sda = InOutPin::new(sda);
+    let mut scl = InOutPin::new(scl);
+
+    for _ in 0..RECOVERY_CLOCKS {
+        if sda.is_high().unwrap() {
+            break;
+        }
+        scl.set_low().unwrap();
+        delay.delay_us(HALF_CLOCK_US);
+        if !release_clock(&mut scl, delay) {
+            warn!("I2C clock held low");
+            break;
+        }
+        delay.delay_us(HALF_CLOCK_US);
+    }
+
+    // STOP, SDA rising while SCL is high.
+    scl.set_low().unwrap();
+    sda.set_low().unwrap();
+    delay.delay_us(HALF_CLOCK_US);
+    release_clock(&mut scl, delay);
+    delay.delay_us(HALF_CLOCK_US);
+    sda.set_high().unwrap();
+    delay.delay_us(HALF_CLOCK_US);
+
+    new(block, sda.release(), scl.release(), frequency, resets, system_clock)
+}
+
+/// The bus, shared by the devices on it.  Each borrows it for a transaction
+/// at a time, only the main loop uses it.
+pub(crate) struct SharedBus {
+    // Only None while the bus is being recovered.
+    i2c: RefCell<Option<I2cBus>>,
+    frequency: Cell<HertzU32>,
+    // Kept to set the controller up again after recovering the bus.
+    resets: RefCell<pac::RESETS>,
+    system_clock: HertzU32,
+    timer: Timer,
+}
+
+impl SharedBus {
+    pub fn new(
+        block: pac::I2C1,
+        sda: SdaPin,
+        scl: SclPin,
+        mut resets: pac::RESETS,
+        system_clock: HertzU32,
+        timer: Timer,
+    ) -> Self {
+        let i2c = new(block, sda, scl, STANDARD_MODE, &mut resets, system_clock);
+        Self {
+            i2c: RefCell::new(Some(i2c)),
+            frequency: Cell::new(STANDARD_MODE),
+            resets: RefCell::new(resets),
+            system_clock,
+            timer,
+        }
+    }
+
+    pub fn device(&self) -> BusDevice<'_> {
+        BusDevice { bus: self }
+    }
+
+    /// Changes the bus speed, for when every device on it can keep up.
+    pub fn set_frequency(&self, frequency: HertzU32) {
+        self.frequency.set(frequency);
+        let mut i2c = self.i2c.borrow_mut();
+        let mut resets = self.resets.borrow_mut();
+        let (block, (sda, scl)) = i2c.take().unwrap().free(&mut resets);
+        *i2c = Some(new(block, sda, scl, frequency, &mut resets, self.system_clock));
+    }
+
+    /// Frees the bus after a device has left it stuck.
+    pub fn recover(&self) {
+        let mut i2c = self.i2c.borrow_mut();
+        let mut timer = self.timer;
+        *i2c = Some(recover(
+            i2c.take().unwrap(),
+            self.frequency.get(),
+            &mut self.resets.borrow_mut(),
+            self.system_clock,
+            &mut timer,
+        ));
+    }
+}
+
+/// A handle on the shared bus for one device's driver.
+#[derive(Clone, Copy)]
+pub(crate) struct BusDevice<'a> {
+    bus: &'a SharedBus,
+}
+
+impl ErrorType for BusDevice<'_> {
+    type Error = rp_pico::hal::i2c::Error;
+}
+
+impl I2c for BusDevice<'_> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.bus.i2c.borrow_mut().as_mut().unwrap().transaction(address, operations)
+    }
+}
@@ -7,10 +7,7 @@ use cortex_m::delay::Delay;
 use cortex_m::interrupt::Mutex;
 use cortex_m::prelude::*;
 
-use debounce::DebounceState;
 use embedded_hal::digital::InputPin;
-use key_table::KeyFunction;
-use key_table::KEY_MAPPING;
 // The macro for our start-up function
 use rp_pico::entry;
 
@@ -26,7 +23,6 @@ use rp_pico::hal::gpio::Pin;
 use rp_pico::hal::gpio::PullDown;
 use rp_pico::hal::gpio::SioInput;
 use rp_pico::hal::gpio::SioOutput;
-use rp_pico::hal::i2c::I2C;
 // Pull in any important traits
 use rp_pico::hal::prelude::*;
 
@@ -47,49 +43,125 @@ use usb_device::{class_prelude::*, prelude::*};
 use usbd_human_interface_device::device::consumer::ConsumerControl;
 use usbd_human_interface_device::device::consumer::ConsumerControlConfig;
 use usbd_human_interface_device::device::consumer::MultipleConsumerReport;
-use usbd_human_interface_device::device::keyboard::NKROBootKeyboard;
-use usbd_human_interface_device::device::keyboard::NKROBootKeyboardConfig;
-use usbd_human_interface_device::device::mouse::WheelMouse;
-use usbd_human_interface_device::device::mouse::WheelMouseConfig;
 use usbd_human_interface_device::device::mouse::WheelMouseReport;
 use usbd_human_interface_device::page::Consumer;
 use usbd_human_interface_device::page::Keyboard;
 use usbd_human_interface_device::prelude::*;
 
-use fugit::RateExtU32;
 
-use embedded_io::Write;
 
 use defmt::{info, warn, error};
 use defmt_rtt as _;
 use panic_probe as _;
 
-use crate::key_table::MouseButton;
-use crate::key_table::MOUSE_MODIFIER_KEYS;
-
-mod debounce;
-mod key_table;
-
-pub(crate) const KEY_ROWS: usize = 6;
-pub(crate) const KEY_COLUMNS: usize = 17;
+use pico_play_core::absolute::absolute_position;
+use pico_play_core::auto_mouse::AutoMouse;
+use pico_play_core::auto_mouse::AutoMouseEvent;
+use pico_play_core::debounce::DebounceState;
+use pico_play_core::key_table::Action;
+use pico_play_core::key_table::Direction;
+use pico_play_core::key_table::FN_KEY_MAPPING;
+use pico_play_core::key_table::KEY_COLUMNS;
+use pico_play_core::key_table::KEY_MAPPING;
+use pico_play_core::key_table::KEY_ROWS;
+use pico_play_core::key_table::KeyFunction;
+use pico_play_core::key_table::MouseButton;
+use pico_play_core::key_table::PointerMode;
+use pico_play_core::key_table::SystemKey;
+use pico_play_core::mouse::MOUSE_REPORT_PERIOD_MS;
+use pico_play_core::mouse::MouseTracker;
+use pico_play_core::mouse::merge_mouse_reports;
+use pico_play_core::mouse_keys::MouseKeys;
+use pico_play_core::report_queue::KeyReportQueue;
+use pico_play_core::report_queue::KeySet;
+use pico_play_core::settings::RolloverMode;
+use pico_play_core::status_screen::Connection;
+use pico_play_core::status_screen::Status;
+use pico_play_core::status_screen::StatusScreen;
+use pico_play_core::typing_speed::TypingSpeed;
+
+use crate::accessories::Accessories;
+use crate::accessories::Board;
+use crate::digitizer::Digitizer;
+use crate::digitizer::DigitizerConfig;
+use crate::digitizer::DigitizerReport;
+use crate::gamepad::Gamepad;
+use crate::gamepad::GamepadConfig;
+use crate::gamepad::GamepadReport;
+use crate::hires_mouse::HiResMouse;
+use crate::hires_mouse::HiResMouseConfig;
+#[cfg(not(feature = "adc-joystick"))]
+use crate::i2c_bus::SharedBus;
+use crate::pointing::LinkEvent;
+use crate::pointing::PointingSource;
+use crate::rollover_keyboard::RolloverKeyboard;
+use crate::rollover_keyboard::RolloverKeyboardConfig;
+use crate::suspend::PowerTransition;
+use crate::suspend::SuspendTracker;
+use crate::system_control::SystemControl;
+use crate::system_control::SystemControlConfig;
+
+#[cfg(all(feature = "adc-joystick", feature = "lcd-backpack"))]
+compile_error!("The LCD backpack needs the I2C pins the ADC joystick uses");
+#[cfg(all(feature = "adc-joystick", feature = "oled"))]
+compile_error!("The OLED needs the I2C pins the ADC joystick uses");
+
+mod accessories;
+#[cfg(feature = "adc-joystick")]
+mod adc_joystick;
+mod digitizer;
+mod flash;
+#[cfg(not(feature = "adc-joystick"))]
+mod gadget;
+#[cfg(not(feature = "adc-joystick"))]
+mod gadget_board;
+mod gamepad;
+#[cfg(not(feature = "adc-joystick"))]
+mod hd44780;
+mod hires_mouse;
+#[cfg(not(feature = "adc-joystick"))]
+mod i2c_bus;
+#[cfg(not(feature = "adc-joystick"))]
+mod oled_screen;
+#[cfg(not(feature = "adc-joystick"))]
+mod peripherals;
+mod pointing;
+#[cfg(not(feature = "adc-joystick"))]
+mod port_expander;
+mod rollover_keyboard;
+#[cfg(not(feature = "adc-joystick"))]
+mod rotary_encoder;
+mod settings;
+#[cfg(not(feature = "adc-joystick"))]
+mod ssd1306;
+mod suspend;
+mod system_control;
 
 /// Period for calling tick() on the USB HID, and scanning the switch matrix.
 const HID_TICK_AND_MATRIX_SCAN_PERIOD_MS: u32 = 1;
-/// Period between sending keyboard reports.
-const KEYBOARD_REPORT_PERIOD_MS: u32 = 10;
-/// Period between sending consumer (media key) reports.
-const CONSUMER_REPORT_PERIOD_MS: u32 = 50;
-
-/// Distance mouse must move before space keys become mouse buttons
-const MOUSENESS_THRESHOLD: u64 = 5;
+/// Polling interval requested for the keyboard endpoint.  Keyboard reports
+/// are sent as soon as the key state changes, so this bounds the latency.
+const KEYBOARD_ENDPOINT_POLL_MS: u32 = 1;
+
+/// Settings are saved this long after the last change, so stepping through
+/// values doesn't wear out the flash.
+const SETTINGS_SAVE_DELAY_MS: u64 = 3000;
+/// How long messages stay on the status screen.
+const MESSAGE_DURATION_MS: u64 = 2000;
+/// Displays are kept up to date this long after the host suspends, long
+/// enough for them all to show Asleep, then left alone until it resumes.
+const ASLEEP_DRAW_MS: u64 = 1000;
 
 type UsbMultiDev = UsbHidClass<
     'static,
     hal::usb::UsbBus,
     HList!(
-        WheelMouse<'static, hal::usb::UsbBus>,
+        Digitizer<'static, hal::usb::UsbBus>,
+        Gamepad<'static, hal::usb::UsbBus>,
+        SystemControl<'static, hal::usb::UsbBus>,
+        HiResMouse<'static, hal::usb::UsbBus>,
         ConsumerControl<'static, hal::usb::UsbBus>,
-        NKROBootKeyboard<'static, hal::usb::UsbBus>
+        RolloverKeyboard<'static, hal::usb::UsbBus>
     ),
 >;
 
@@ -157,6 +229,9 @@ fn main() -> ! {
     }
 
 
+    let mut settings = settings::load();
+    info!("Settings: {}", settings);
+
     static USB_ALLOC: StaticCell<UsbBusAllocator<hal::usb::UsbBus>> = StaticCell::new();
     let usb_alloc = USB_ALLOC.init(UsbBusAllocator::new(hal::usb::UsbBus::new(
                 pac.USBCTRL_REGS,
@@ -168,9 +243,12 @@ fn main() -> ! {
 
     {
         let multi = UsbHidClassBuilder::new()
-            .add_device(NKROBootKeyboardConfig::default())
+            .add_device(RolloverKeyboardConfig::new(settings.rollover, KEYBOARD_ENDPOINT_POLL_MS.millis()))
             .add_device(ConsumerControlConfig::default())
-            .add_device(WheelMouseConfig::default())
+            .add_device(HiResMouseConfig::default())
+            .add_device(SystemControlConfig::default())
+            .add_device(GamepadConfig::default())
+            .add_device(DigitizerConfig::default())
             .build(usb_alloc);
 
         cortex_m::interrupt::free(|cs| {
@@ -183,6 +261,7 @@ fn main() -> ! {
             UsbDeviceBuilder::new(usb_alloc, UsbVidPid(0x1209, 0x0001))
                 .strings(&[StringDescriptors::default().product("Crappy Keyboard")])
                 .unwrap()
+                .supports_remote_wakeup(true)
                 .build();
 
         cortex_m::interrupt::free(|cs| {
@@ -190,20 +269,27 @@ fn main() -> ! {
         });
     }
 
-    let sda_pin: hal::gpio::Pin<_, hal::gpio::FunctionI2C, _> = pins.gpio26.reconfigure();
-    let scl_pin: hal::gpio::Pin<_, hal::gpio::FunctionI2C, _> = pins.gpio27.reconfigure();
-
-
-    //let sda_pin = pins.gpio26.into_function::<FunctionI2C>();
-    //let scl_pin = pins.gpio27.into_function::<FunctionI2C>();
-    // Create I2C peripheral - using I2C1 for GP26/GP27
-    let mut i2c = I2C::i2c1(
-        pac.I2C1,
-        sda_pin,
-        scl_pin,
-        100.kHz(), // I2C clock frequency
-        &mut pac.RESETS,
-        &clocks.peripheral_clock,
+    // The gadget board and any other accessories are on I2C1 on GP26/GP27.
+    #[cfg(not(feature = "adc-joystick"))]
+    let i2c_bus: &'static SharedBus = {
+        static I2C_BUS: StaticCell<SharedBus> = StaticCell::new();
+        I2C_BUS.init(SharedBus::new(
+            pac.I2C1,
+            pins.gpio26.reconfigure(),
+            pins.gpio27.reconfigure(),
+            pac.RESETS,
+            clocks.peripheral_clock.freq(),
+            timer,
+        ))
+    };
+    #[cfg(not(feature = "adc-joystick"))]
+    let mut board = Board::scan(i2c_bus, timer);
+    #[cfg(feature = "adc-joystick")]
+    let mut board = Board::new(
+        hal::Adc::new(pac.ADC, &mut pac.RESETS),
+        pins.gpio26,
+        pins.gpio27,
+        pins.gpio28,
     );
 
     // The delay object lets us wait for specified amounts of time (in
@@ -239,19 +325,16 @@ fn main() -> ! {
         &mut pins.gpio16.into_pull_down_input().into_dyn_pin(),
     ];
 
-    let mut debounce_states: [[DebounceState; KEY_COLUMNS]; KEY_ROWS] = Default::default();
+    let mut matrix_state: MatrixState = Default::default();
 
     let mut hid_tick_and_scan_count_down = timer.count_down();
     hid_tick_and_scan_count_down.start(HID_TICK_AND_MATRIX_SCAN_PERIOD_MS.millis());
 
-    let mut keyboard_count_down = timer.count_down();
-    keyboard_count_down.start(KEYBOARD_REPORT_PERIOD_MS.millis());
-
-    let mut consumer_count_down = timer.count_down();
-    consumer_count_down.start(CONSUMER_REPORT_PERIOD_MS.millis());
-
+    let mut mouse_count_down = timer.count_down();
+    mouse_count_down.start(MOUSE_REPORT_PERIOD_MS.millis());
 
-    let mut previous_consumer_report: MultipleConsumerReport = Default::default();
+    let mut previous_reports: PreviousReports = Default::default();
+    let mut previous_mouse_buttons: u8 = 0;
 
     // Enable the USB interrupt
     unsafe {
@@ -259,15 +342,52 @@ fn main() -> ! {
     };
 
     let mut buffers: ScanBuffers = Default::default();
-    // Distance mouse has moved without a normal key being pressed.
-    let mut mouseness: u64 = 0;
+    let mut auto_mouse: AutoMouse = Default::default();
     let mut press_counter: u64 = 0;
+    let mut typing_speed: TypingSpeed = Default::default();
+    let mut status_screen: StatusScreen = Default::default();
+    // Turns of a rotary encoder not yet sent as scrolling.
+    let mut wheel_detents: i32 = 0;
+    // The encoder counts turns made while suspended, to be thrown away.
+    let mut turns_while_asleep = false;
     let mut mouse_tracker: MouseTracker = Default::default();
+    let mut mouse_keys: MouseKeys = Default::default();
     let mut scan_clock: u64 = 0;
-
-    //i2c.write(0x08u8, b"binky");
+    // Time of the latest scan.
+    let mut now_ms: u64 = 0;
+    // Time until which displays are drawn while suspended.
+    let mut asleep_draw_until_ms: u64 = 0;
+    let mut suspend_tracker: SuspendTracker = Default::default();
+    let mut key_queue: KeyReportQueue = Default::default();
+    // Scan clock at which to save changed settings.
+    let mut settings_save_at: Option<u64> = None;
+    let mut recalibrate_joystick = false;
+    // Mouse buttons held down by drag-lock, as report bits.
+    let mut locked_buttons: u8 = 0;
+    // Pointer modes switched on by a Toggle key.
+    let mut toggled_modes: heapless::Vec<PointerMode, 2> = heapless::Vec::new();
 
     loop {
+        let (usb_state, remote_wakeup_enabled) = cortex_m::interrupt::free(|cs| {
+            USB_DEV.borrow(cs).borrow().as_ref()
+                .map(|d| (d.state(), d.remote_wakeup_enabled()))
+                .unwrap_or((UsbDeviceState::Default, false))
+        });
+
+        match suspend_tracker.update_state(usb_state) {
+            PowerTransition::None => {}
+            PowerTransition::Suspended => {
+                info!("USB suspended");
+                set_led(false);
+                asleep_draw_until_ms = now_ms + ASLEEP_DRAW_MS;
+            }
+            PowerTransition::Resumed => {
+                info!("USB resumed");
+                set_led(auto_mouse.is_active(&settings.auto_mouse));
+                turns_while_asleep = true;
+            }
+        }
+
         if hid_tick_and_scan_count_down.wait().is_ok() {
             cortex_m::interrupt::free(|cs| {
                 let mut x = MULTI_DEV.borrow(cs).borrow_mut();
@@ -282,81 +402,330 @@ fn main() -> ! {
             });
 
             scan_clock += 1;
+            now_ms = scan_clock * u64::from(HID_TICK_AND_MATRIX_SCAN_PERIOD_MS);
             let press_counter_previous = press_counter;
             scan_keys(
                 &mut row_pins,
                 &mut column_pins,
                 &mut delay,
-                &mut debounce_states,
+                &mut matrix_state,
                 &mut buffers,
-                mouseness >= MOUSENESS_THRESHOLD,
+                auto_mouse.is_active(&settings.auto_mouse),
                 scan_clock,
-                || press_counter += 1
+                || {
+                    press_counter += 1;
+                    typing_speed.press(now_ms);
+                }
             );
 
-            if press_counter != press_counter_previous && true {
-                let mut bytes = [0u8; 12];
-                let _ = write!(bytes.as_mut_slice(), "{}", press_counter);
-                let len = bytes.iter().take_while(|n| **n != 0u8).count();
-                let _ = i2c.write(0x08u8, &bytes[..len]);
+            key_queue.push(&buffers.key_codes);
+
+            if suspend_tracker.should_wake(press_counter != press_counter_previous, remote_wakeup_enabled) {
+                info!("Signalling remote wakeup");
+                cortex_m::interrupt::free(|cs| {
+                    if let Some(usb_dev) = USB_DEV.borrow(cs).borrow().as_ref() {
+                        usb_dev.bus().remote_wakeup();
+                    }
+                });
+            }
+
+            buffers.actions.iter().for_each(|a| match a {
+                Action::CycleMouseCurve => {
+                    settings.pointer.curve = settings.pointer.curve.next();
+                    info!("Mouse curve {}", settings.pointer.curve);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::MouseSensitivityUp | Action::MouseSensitivityDown => {
+                    settings.pointer.adjust_sensitivity(*a == Action::MouseSensitivityUp);
+                    info!("Mouse sensitivity {}%", settings.pointer.sensitivity);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::MouseMaxSpeedUp | Action::MouseMaxSpeedDown => {
+                    settings.pointer.adjust_max_speed(*a == Action::MouseMaxSpeedUp);
+                    info!("Mouse max speed {} px/s", settings.pointer.max_speed);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::CyclePrecisionDivisor => {
+                    settings.pointer.cycle_precision_divisor();
+                    info!("Precision divisor {}", settings.pointer.precision_divisor);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::RotateJoystick(_) | Action::SwapJoystickAxes | Action::InvertJoystickX | Action::InvertJoystickY => {
+                    let orientation = &mut settings.pointer.orientation;
+                    match a {
+                        Action::RotateJoystick(degrees) => orientation.rotate(*degrees),
+                        Action::SwapJoystickAxes => orientation.swap_axes = !orientation.swap_axes,
+                        Action::InvertJoystickX => orientation.invert_x = !orientation.invert_x,
+                        Action::InvertJoystickY => orientation.invert_y = !orientation.invert_y,
+                        _ => unreachable!(),
+                    }
+                    info!("Joystick orientation {}", settings.pointer.orientation);
+                    // The centre moves with the axes.
+                    recalibrate_joystick = true;
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::SetAbsoluteCentre => {
+                    settings.pointer.absolute_centre =
+                        absolute_position(&settings.pointer.absolute_centre, mouse_tracker.deflection());
+                    info!("Absolute centre {}", settings.pointer.absolute_centre);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::CycleFilter => {
+                    settings.pointer.filter = settings.pointer.filter.next();
+                    info!("Joystick filter {}", settings.pointer.filter);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::CycleDeadzoneShape => {
+                    let deadzone = &mut settings.pointer.deadzone;
+                    deadzone.shape = deadzone.shape.next();
+                    info!("Deadzone shape {}", deadzone.shape);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::DeadzoneRadiusUp | Action::DeadzoneRadiusDown => {
+                    settings.pointer.deadzone.adjust_radius(*a == Action::DeadzoneRadiusUp);
+                    info!("Deadzone radius {}", settings.pointer.deadzone.radius);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::AutoMouseDistanceUp | Action::AutoMouseDistanceDown => {
+                    settings.auto_mouse.adjust_activation_distance(*a == Action::AutoMouseDistanceUp);
+                    info!("Mouse mode after {} px", settings.auto_mouse.activation_distance);
+                    set_led(auto_mouse.is_active(&settings.auto_mouse));
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::AutoMouseTimeoutUp | Action::AutoMouseTimeoutDown => {
+                    settings.auto_mouse.adjust_timeout(*a == Action::AutoMouseTimeoutUp);
+                    info!("Mouse mode timeout {} ms", settings.auto_mouse.timeout_ms);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::CycleJoystickButton => {
+                    settings.pointer.joystick_button = settings.pointer.joystick_button.next();
+                    info!("Joystick button clicks {}", settings.pointer.joystick_button);
+                    settings_save_at = Some(scan_clock + SETTINGS_SAVE_DELAY_MS);
+                }
+                Action::DragLock(button) => {
+                    locked_buttons ^= button.bit();
+                    info!("Drag lock {} {}", button, locked_buttons & button.bit() != 0);
+                }
+                Action::CalibrateJoystick if !board.is_connected() => {
+                    warn!("No joystick to calibrate");
+                }
+                Action::CalibrateJoystick => {
+                    info!("Calibrating joystick");
+                    mouse_tracker.start_calibration();
+                    // Until calibration finishes or times out, the result replaces it.
+                    status_screen.show_message(now_ms, b"Push stick round", 30_000);
+                }
+                Action::RecalibrateJoystick => {
+                    info!("Recalibrating joystick");
+                    recalibrate_joystick = true;
+                }
+                Action::Toggle(mode) => {
+                    if let Some(i) = toggled_modes.iter().position(|m| m == mode) {
+                        toggled_modes.swap_remove(i);
+                        info!("{} off", mode);
+                    } else {
+                        toggled_modes.push(*mode).unwrap();
+                        info!("{} on", mode);
+                    }
+                }
+                Action::ToggleRollover => {
+                    settings.rollover = match settings.rollover {
+                        RolloverMode::Nkro => RolloverMode::Boot6kro,
+                        RolloverMode::Boot6kro => RolloverMode::Nkro,
+                    };
+                    info!("Switching to {}, rebooting", settings.rollover);
+                    settings::save(&settings);
+                    // The host only reads the report descriptor when we enumerate.
+                    cortex_m::peripheral::SCB::sys_reset();
+                }
+            });
+
+            if settings_save_at.is_some_and(|t| scan_clock >= t) {
+                info!("Saving settings");
+                settings::save(&settings);
+                settings_save_at = None;
+            }
+
+            if !suspend_tracker.is_suspended() {
+                send_key_reports(&mut key_queue, &buffers, &mut previous_reports);
+            }
+
+            if !suspend_tracker.is_suspended() || now_ms < asleep_draw_until_ms {
+                let caps_lock = cortex_m::interrupt::free(|cs| {
+                    MULTI_DEV.borrow(cs).borrow_mut().as_mut()
+                        .is_some_and(|multi| multi.device::<RolloverKeyboard<'_, _>, _>().leds().caps_lock)
+                });
+                let connection = if usb_state != UsbDeviceState::Configured {
+                    Connection::NoUsb
+                } else if suspend_tracker.is_suspended() {
+                    Connection::Asleep
+                } else if !board.is_connected() {
+                    Connection::NoJoystick
+                } else {
+                    Connection::Connected
+                };
+                let status = Status {
+                    fn_layer: matrix_state.fn_held,
+                    caps_lock,
+                    mouse_mode: auto_mouse.is_active(&settings.auto_mouse),
+                    words_per_minute: typing_speed.words_per_minute(now_ms),
+                    connection,
+                };
+                status_screen.update(now_ms, &status, board.text_display());
+                let deflection = board.is_connected().then(|| mouse_tracker.deflection());
+                let input = board.update(now_ms, &status, deflection);
+                // Turns while suspended would all scroll at once on resuming.
+                if !suspend_tracker.is_suspended() && !core::mem::take(&mut turns_while_asleep) {
+                    wheel_detents = wheel_detents.saturating_add(input.wheel_detents);
+                }
             }
         }
 
-        if keyboard_count_down.wait().is_ok() {
+        if mouse_count_down.wait().is_ok() && !suspend_tracker.is_suspended() {
             let mut mouse_report = WheelMouseReport::default();
+            // The joystick and mouse keys' shares of mouse_report.
+            let mut joystick_report = WheelMouseReport::default();
+            let mut keys_report = WheelMouseReport::default();
+            // Centred with no buttons unless gamepad mode is on.
+            let mut gamepad_report = GamepadReport::default();
+            // Out of range unless absolute mode is on.
+            let mut digitizer_report = DigitizerReport::default();
+            // Whether the host has enabled high resolution scrolling.
+            let wheel_multiplier = cortex_m::interrupt::free(|cs| {
+                MULTI_DEV.borrow(cs).borrow_mut().as_mut()
+                    .map(|multi| multi.device::<HiResMouse<'_, _>, _>().wheel_multiplier())
+                    .unwrap_or_default()
+            });
 
-            if (scan_clock * u64::from(HID_TICK_AND_MATRIX_SCAN_PERIOD_MS)) >= 1500 {
+            if now_ms >= 1500 {
                 // Update the mouse only if we have been running for more that 1.5 seconds.
                 // The joystick origin is garbage shortly after boot.
-                mouse_tracker.update(&mut i2c, false);
+                let mode_active = |mode| buffers.held_modes.contains(&mode) || toggled_modes.contains(&mode);
+                mouse_tracker.set_scrolling(mode_active(PointerMode::Scroll));
+                mouse_tracker.set_precision(mode_active(PointerMode::Precision));
+                let gamepad_mode = mode_active(PointerMode::Gamepad);
+                let absolute_mode = mode_active(PointerMode::Absolute);
+                mouse_tracker.set_analog(gamepad_mode || absolute_mode);
+                let poll = board.poll(now_ms);
+                match poll.event {
+                    None => {}
+                    Some(LinkEvent::Connected) => {
+                        info!("Joystick connected");
+                        mouse_tracker.restart();
+                        // The LCD may have been reset too.
+                        status_screen.invalidate();
+                    }
+                    Some(LinkEvent::Disconnected) => {
+                        warn!("Joystick disconnected");
+                        mouse_tracker.restart();
+                    }
+                }
+                let calibration = poll.reading
+                    .and_then(|raw| mouse_tracker.update(raw, recalibrate_joystick, &settings.pointer));
+                recalibrate_joystick = false;
+                match calibration {
+                    None => {}
+                    Some(Ok(c)) => {
+                        info!("Joystick calibrated {}", c);
+                        settings.pointer.calibration = Some(c);
+                        settings_save_at = Some(scan_clock);
+                        status_screen.show_message(now_ms, b"Calibrated", MESSAGE_DURATION_MS);
+                    }
+                    Some(Err(e)) => {
+                        warn!("Joystick calibration failed {}", e);
+                        status_screen.show_message(now_ms, b"Calibrate failed", MESSAGE_DURATION_MS);
+                    }
+                }
 
-                mouse_tracker.populate_report(&mut mouse_report);
-                buffers.mouse_buttons.iter().for_each(|b|{
-                    match b {
-                        MouseButton::Left => mouse_report.buttons |= 0x1,
-                        MouseButton::Right => mouse_report.buttons |= 0x2
+                if gamepad_mode {
+                    gamepad_report.set_stick(mouse_tracker.deflection());
+                    if mouse_tracker.button() {
+                        gamepad_report.press(1);
                     }
-                });
+                    buffers.gamepad_buttons.iter().for_each(|b| gamepad_report.press(*b));
+                }
 
-                mouseness += u64::try_from(mouse_report.x.abs()).unwrap();
-                mouseness += u64::try_from(mouse_report.y.abs()).unwrap();
+                if absolute_mode {
+                    let position = absolute_position(&settings.pointer.absolute_centre, mouse_tracker.deflection());
+                    digitizer_report = DigitizerReport {
+                        tip: mouse_tracker.button(),
+                        in_range: true,
+                        x: position.x,
+                        y: position.y,
+                    };
+                }
 
-                if mouseness != 0 {
-                    if buffers.consumer_codes.is_empty() && buffers.key_codes.iter().all(|k| MOUSE_MODIFIER_KEYS.contains(k)) {
-                        // No consumer keys pressed, all keyboard keys are modifiers.
-                        // stay in mouse mode
-                    } else {
-                        mouseness = 0;
+                mouse_keys.update(&buffers.mouse_moves, &buffers.mouse_wheels);
+                mouse_keys.turn_wheel(core::mem::take(&mut wheel_detents));
+
+                mouse_tracker.populate_report(&mut joystick_report, &settings.pointer, &wheel_multiplier);
+                mouse_keys.populate_report(&mut keys_report, &wheel_multiplier);
+                mouse_report = merge_mouse_reports(&joystick_report, &mut keys_report);
+                buffers.mouse_buttons.iter().for_each(|b| mouse_report.buttons |= b.bit());
+                mouse_report.buttons |= locked_buttons;
+
+                let distance = [mouse_report.x, mouse_report.y, mouse_report.vertical_wheel, mouse_report.horizontal_wheel]
+                    .iter()
+                    .map(|d| u64::from(d.unsigned_abs()))
+                    .sum();
+                let event = auto_mouse.update(
+                    now_ms,
+                    distance,
+                    mouse_report.buttons != 0,
+                    &buffers.key_codes,
+                    !buffers.consumer_codes.is_empty(),
+                    &settings.auto_mouse,
+                );
+                match event {
+                    None => {}
+                    Some(AutoMouseEvent::Entered) => {
+                        info!("Mouse mode");
+                        set_led(true);
+                    }
+                    Some(AutoMouseEvent::Exited) => {
+                        info!("Keyboard mode");
+                        set_led(false);
                     }
                 }
             }
 
             cortex_m::interrupt::free(|cs| {
-                if let Some(led_pin) = LED_PIN.borrow(cs).borrow_mut().as_mut() {
-                    if mouseness >= MOUSENESS_THRESHOLD {
-                        led_pin.set_high().unwrap();
-                    } else {
-                        led_pin.set_low().unwrap();
-                    }
-                }
+                if let Some(multi) = MULTI_DEV.borrow(cs).borrow_mut().as_mut() {
+                    let gamepad = multi.device::<Gamepad<'_, _>, _>();
 
-                let mut x = MULTI_DEV.borrow(cs).borrow_mut();
-                if let Some(multi) = x.as_mut() {
+                    match gamepad.write_report(&gamepad_report) {
+                        Ok(_) => {}
+                        Err(UsbHidError::WouldBlock) => {}
+                        Err(UsbHidError::Duplicate) => {}
+                        Err(_) => panic!("Gamepad write failure."),
+                    }
 
-                    let keyboard = multi.device::<NKROBootKeyboard<'_, _>, _>();
+                    let digitizer = multi.device::<Digitizer<'_, _>, _>();
 
-                    match keyboard.write_report(buffers.key_codes.iter().copied()) {
+                    match digitizer.write_report(&digitizer_report) {
                         Ok(_) => {}
                         Err(UsbHidError::WouldBlock) => {}
                         Err(UsbHidError::Duplicate) => {}
-                        Err(_) => panic!("Keyboard write failure."),
+                        Err(_) => panic!("Digitizer write failure."),
                     }
+                }
+
+                // Only report when something changed, an all-zero report is a no-op for the host.
+                let has_motion = mouse_report.x != 0 || mouse_report.y != 0
+                    || mouse_report.vertical_wheel != 0 || mouse_report.horizontal_wheel != 0;
+                if !has_motion && mouse_report.buttons == previous_mouse_buttons {
+                    return;
+                }
 
-                    let mouse = multi.device::<WheelMouse<'_, _>, _>();
+                let mut x = MULTI_DEV.borrow(cs).borrow_mut();
+                if let Some(multi) = x.as_mut() {
+                    let mouse = multi.device::<HiResMouse<'_, _>, _>();
 
                     match mouse.write_report(&mouse_report) {
                         Ok(_) => {
-                            mouse_tracker.account_report(&mouse_report);
+                            mouse_tracker.account_report(&joystick_report, &wheel_multiplier);
+                            mouse_keys.account_report(&keys_report, &wheel_multiplier);
+                            previous_mouse_buttons = mouse_report.buttons;
                         }
                         Err(UsbHidError::WouldBlock) => {}
                         Err(UsbHidError::Duplicate) => {}
@@ -366,139 +735,138 @@ fn main() -> ! {
             });
         }
 
-        if consumer_count_down.wait().is_ok() {
-            let mut consumer_report = MultipleConsumerReport::default();
-            let len = usize::min(buffers.consumer_codes.len(), consumer_report.codes.len());
-            consumer_report.codes[..len]
-                .copy_from_slice(&buffers.consumer_codes.as_slice()[..len]);
+        // Avoid busylooping, we poll the timers at 10KHz.
+        delay.delay_us(100);
+    }
+}
 
-            cortex_m::interrupt::free(|cs| {
-                let mut x = MULTI_DEV.borrow(cs).borrow_mut();
-                if let Some(multi) = x.as_mut() {
+/// Turns the onboard LED on or off, if it has been set up yet.
+fn set_led(on: bool) {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(led_pin) = LED_PIN.borrow(cs).borrow_mut().as_mut() {
+            led_pin.set_state(on.into()).unwrap();
+        }
+    });
+}
 
-                    let consumer = multi.device::<ConsumerControl<'_, _>, _>();
+// Reports last accepted by the consumer and system control endpoints.
+#[derive(Default)]
+struct PreviousReports {
+    consumer: MultipleConsumerReport,
+    system: Option<SystemKey>,
+}
 
-                    match consumer.write_report(&consumer_report) {
-                        Ok(_) => {
-                            previous_consumer_report = consumer_report;
-                        }
-                        Err(UsbError::WouldBlock) => {}
-                        Err(_) => panic!("Consumer write failure."),
+/// Send keyboard, consumer and system reports if the key state has changed
+/// since the last report.  Reports the endpoint isn't ready for are retried
+/// on the next scan.
+fn send_key_reports(
+    key_queue: &mut KeyReportQueue,
+    buffers: &ScanBuffers,
+    previous_reports: &mut PreviousReports
+) {
+    let mut consumer_report = MultipleConsumerReport::default();
+    let len = usize::min(buffers.consumer_codes.len(), consumer_report.codes.len());
+    consumer_report.codes[..len]
+        .copy_from_slice(&buffers.consumer_codes.as_slice()[..len]);
+
+    cortex_m::interrupt::free(|cs| {
+        let mut x = MULTI_DEV.borrow(cs).borrow_mut();
+        if let Some(multi) = x.as_mut() {
+            if key_queue.has_pending() {
+                let keyboard = multi.device::<RolloverKeyboard<'_, _>, _>();
+
+                match keyboard.write_report(key_queue.next_report().iter().copied()) {
+                    Ok(_) | Err(UsbHidError::Duplicate) => {
+                        key_queue.report_sent();
                     }
+                    Err(UsbHidError::WouldBlock) => {}
+                    Err(_) => panic!("Keyboard write failure."),
                 }
+            }
 
-            });
-        }
+            if consumer_report != previous_reports.consumer {
+                let consumer = multi.device::<ConsumerControl<'_, _>, _>();
 
-        // Avoid busylooping, we poll the timers at 10KHz.
-        delay.delay_us(100);
-    }
+                match consumer.write_report(&consumer_report) {
+                    Ok(_) => {
+                        previous_reports.consumer = consumer_report;
+                    }
+                    Err(UsbError::WouldBlock) => {}
+                    Err(_) => panic!("Consumer write failure."),
+                }
+            }
+
+            // The system control report only has room for one key.
+            let system_report = buffers.system_keys.first().copied();
+            if system_report != previous_reports.system {
+                let system = multi.device::<SystemControl<'_, _>, _>();
+
+                match system.write_report(system_report) {
+                    Ok(_) | Err(UsbHidError::Duplicate) => {
+                        previous_reports.system = system_report;
+                    }
+                    Err(UsbHidError::WouldBlock) => {}
+                    Err(_) => panic!("System control write failure."),
+                }
+            }
+        }
+    });
 }
 
 // The result of scanning which keys are pressed.
 #[derive(Default)]
 struct ScanBuffers {
     // Way more than we ever need.
-    key_codes: heapless::Vec<Keyboard, 32>,
+    key_codes: KeySet,
     consumer_codes: heapless::Vec<Consumer, 10>,
-    mouse_buttons: heapless::Vec<MouseButton, 2>,
+    system_keys: heapless::Vec<SystemKey, 3>,
+    mouse_buttons: heapless::Vec<MouseButton, 5>,
+    // Held mouse key directions.
+    mouse_moves: heapless::Vec<Direction, 4>,
+    mouse_wheels: heapless::Vec<Direction, 4>,
+    gamepad_buttons: heapless::Vec<u8, 16>,
+    // Pointer modes whose Hold keys are down.
+    held_modes: heapless::Vec<PointerMode, 2>,
+    // Actions whose keys were pressed during this scan.
+    actions: heapless::Vec<Action, 4>,
 }
 
 impl ScanBuffers {
     fn clear(&mut self) {
         self.key_codes.clear();
         self.consumer_codes.clear();
+        self.system_keys.clear();
         self.mouse_buttons.clear();
+        self.mouse_moves.clear();
+        self.mouse_wheels.clear();
+        self.gamepad_buttons.clear();
+        self.held_modes.clear();
+        self.actions.clear();
     }
 }
 
-#[derive(Default, Clone)]
-struct Point2D<T> {
-    x: T,
-    y: T
-}
-
-type ButtonState = bool;
-
+// State of the switch matrix carried between scans.
 #[derive(Default)]
-struct MouseTracker {
-    unreported_movement: Point2D<i64>,
-    button: ButtonState,
-    origin: Option<Point2D<i16>>
-}
-
-fn mouse_curve(d: i16) -> i32 {
-    if false {
-        let d = i32::from(d);
-        d*d*d.signum()
-    } else {
-        i32::from(d) * 500
-    }
-}
-
-/// Bigger means slower cursor.
-static MOUSE_REPORT_SCALE: i64 = 40_000;
-
-impl MouseTracker {
-    // Read mouse position from i2c, updating internal measurement of movement.
-    fn update(&mut self, i2c: &mut impl embedded_hal::i2c::I2c, reset: bool) {
-        if let Some(raw) = read_mouse_raw(i2c) {
-            self.button = raw.1;
-            if self.origin.is_none() || reset {
-                self.origin = Some(raw.0.clone());
-            }
-
-            let raw = raw.0;
-            let origin = self.origin.as_ref().unwrap();
-            self.unreported_movement.x += i64::from(mouse_curve(raw.x - origin.x));
-            self.unreported_movement.y += i64::from(mouse_curve(raw.y - origin.y));
-        }
-    }
-
-    fn populate_report(&self, report: &mut WheelMouseReport) {
-        let rx = (self.unreported_movement.x / MOUSE_REPORT_SCALE).clamp(i8::MIN.into(), i8::MAX.into());
-        let ry = (self.unreported_movement.y / MOUSE_REPORT_SCALE).clamp(i8::MIN.into(), i8::MAX.into());
-
-        report.x = i8::try_from(rx).unwrap();
-        report.y = i8::try_from(ry).unwrap();
-        report.buttons = if self.button { 0x1 } else { 0x0 };
-    }
-
-    fn account_report(&mut self, report: &WheelMouseReport) {
-        self.unreported_movement.x -= i64::from(report.x) * MOUSE_REPORT_SCALE;
-        self.unreported_movement.y -= i64::from(report.y) * MOUSE_REPORT_SCALE;
-    }
-}
-
-// The raw reading from the Arduino about joystick position and button state.
-fn read_mouse_raw(i2c: &mut impl embedded_hal::i2c::I2c) -> Option<(Point2D<i16>, ButtonState)> {
-    let mut mouse_buffer: [u8; 5] = [0u8; 5];
-
-    match i2c.read(0x08u8, mouse_buffer.as_mut_slice()) {
-        Ok(_) => {
-            Some((
-                Point2D::<i16>{
-                  x: -i16::try_from(u16::from_be_bytes(mouse_buffer[2..4].try_into().unwrap())).unwrap(),
-                  y: i16::try_from(u16::from_be_bytes(mouse_buffer[0..2].try_into().unwrap())).unwrap()
-                },
-                mouse_buffer[4] == 1u8
-            ))
-        },
-        Err(_) => None,
-    }
+struct MatrixState {
+    debounce_states: [[DebounceState; KEY_COLUMNS]; KEY_ROWS],
+    // Whether each key was pressed while Fn was held.
+    fn_latched: [[bool; KEY_COLUMNS]; KEY_ROWS],
+    fn_held: bool,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn scan_keys<F: FnMut()>(
     row_pins: &mut [&mut Pin<DynPinId, FunctionSio<SioOutput>, PullDown>; KEY_ROWS],
     column_pins: &mut [&mut Pin<DynPinId, FunctionSio<SioInput>, PullDown>; KEY_COLUMNS],
     delay: &mut Delay,
-    debounce_states: &mut [[DebounceState; KEY_COLUMNS]; KEY_ROWS],
+    matrix_state: &mut MatrixState,
     buffers: &mut ScanBuffers,
     mouseish: bool,
     scan_clock: u64,
     mut press_action: F
-) -> () {
+) {
     buffers.clear();
+    let mut fn_held = false;
 
     assert_eq!(KEY_MAPPING.len(), row_pins.len());
     for (row_idx, row_mapping) in KEY_MAPPING.iter().enumerate() {
@@ -506,13 +874,31 @@ fn scan_keys<F: FnMut()>(
         delay.delay_us(1);
 
         assert_eq!(row_mapping.len(), column_pins.len());
-        for (col_idx, function) in row_mapping.iter().enumerate() {
+        for (col_idx, base_function) in row_mapping.iter().enumerate() {
             let input = column_pins[col_idx].is_high().unwrap();
-            let is_depressed = debounce_states[row_idx][col_idx].update(input, scan_clock, &mut press_action);
+            let mut pressed_now = false;
+            let is_depressed = matrix_state.debounce_states[row_idx][col_idx].update(input, scan_clock, || {
+                pressed_now = true;
+                press_action();
+            });
+
+            // Fn is taken from the previous scan, it may be on a later row.
+            if pressed_now {
+                matrix_state.fn_latched[row_idx][col_idx] = matrix_state.fn_held;
+            }
+            let function = match &FN_KEY_MAPPING[row_idx][col_idx] {
+                KeyFunction::Transparent => base_function,
+                f if matrix_state.fn_latched[row_idx][col_idx] => f,
+                _ => base_function,
+            };
 
             match (function, is_depressed) {
                 (_, false) => {}
                 (KeyFunction::Nothing, _) => {}
+                (KeyFunction::Transparent, _) => {}
+                (KeyFunction::Fn, true) => {
+                    fn_held = true;
+                }
                 (KeyFunction::Key(Keyboard::NoEventIndicated), _) => {}
                 (KeyFunction::Key(key), true) => {
                                 if !buffers.key_codes.contains(key) {
@@ -522,6 +908,11 @@ fn scan_keys<F: FnMut()>(
                 (KeyFunction::Media(consumer), true) => {
                                 buffers.consumer_codes.push(*consumer).unwrap();
                             }
+                (KeyFunction::System(key), true) => {
+                    if !buffers.system_keys.contains(key) {
+                        buffers.system_keys.push(*key).unwrap();
+                    }
+                }
                 (KeyFunction::MultiKey(keys), true) => {
                     keys.iter().for_each(|k| {
                         if !buffers.key_codes.contains(k) {
@@ -531,17 +922,50 @@ fn scan_keys<F: FnMut()>(
                 },
                 (KeyFunction::Dual(key, mouse_button), true) => {
                     if mouseish {
-                        buffers.mouse_buttons.push(*mouse_button).unwrap();
+                        if !buffers.mouse_buttons.contains(mouse_button) {
+                            buffers.mouse_buttons.push(*mouse_button).unwrap();
+                        }
                     } else {
                         if !buffers.key_codes.contains(key) {
                             buffers.key_codes.push(*key).unwrap();
                         }
                     }
                 }
+                (KeyFunction::MouseMove(direction), true) => {
+                    if !buffers.mouse_moves.contains(direction) {
+                        buffers.mouse_moves.push(*direction).unwrap();
+                    }
+                }
+                (KeyFunction::MouseWheel(direction), true) => {
+                    if !buffers.mouse_wheels.contains(direction) {
+                        buffers.mouse_wheels.push(*direction).unwrap();
+                    }
+                }
+                (KeyFunction::MouseClick(button), true) => {
+                    if !buffers.mouse_buttons.contains(button) {
+                        buffers.mouse_buttons.push(*button).unwrap();
+                    }
+                }
+                (KeyFunction::GamepadButton(button), true) => {
+                    if !buffers.gamepad_buttons.contains(button) {
+                        buffers.gamepad_buttons.push(*button).unwrap();
+                    }
+                }
+                (KeyFunction::Hold(mode), true) => {
+                    if !buffers.held_modes.contains(mode) {
+                        buffers.held_modes.push(*mode).unwrap();
+                    }
+                }
+                (KeyFunction::Action(action), true) => {
+                    if pressed_now {
+                        buffers.actions.push(*action).unwrap();
+                    }
+                }
             }
         }
         row_pins[row_idx].set_low().unwrap();
     }
+    matrix_state.fn_held = fn_held;
 }
 
 #[allow(non_snake_case)]
@@ -554,7 +978,7 @@ fn USBCTRL_IRQ() {
             let Some(multi) = multi.as_mut() {
 
             while usb_dev.poll(&mut [multi]) {
-                let keyboard = multi.device::<NKROBootKeyboard<'_, _>, _>();
+                let keyboard = multi.device::<RolloverKeyboard<'_, _>, _>();
                 match keyboard.read_report() {
                     Ok(_leds) => {}
                     Err(UsbError::WouldBlock) => {}
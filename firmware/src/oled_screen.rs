@@ -0,0 +1,84 @@
+// The status on an SSD1306 OLED, as `render` draws it.  Only the chunks of
+// the picture which changed are sent, one per update so the scan loop isn't
+// held up.
+
+use heapless::HistoryBuf;
+
+use pico_play_core::framebuffer::{Chunks, Framebuffer, WIDTH};
+use pico_play_core::mouse::Point2D;
+use pico_play_core::status_picture::render;
+use pico_play_core::status_screen::Status;
+
+use crate::ssd1306::Ssd1306;
+
+pub(crate) const OLED_HEIGHT: usize = if cfg!(feature = "oled-128x64") { 64 } else { 32 };
+/// Often enough for the crosshair to follow the stick.
+const DRAW_INTERVAL_MS: u64 = 50;
+/// Each column of the graph.
+const SAMPLE_INTERVAL_MS: u64 = 1000;
+/// How often to try the display again after a failure.
+const RETRY_INTERVAL_MS: u64 = 1000;
+
+pub(crate) struct OledScreen {
+    frame: Framebuffer,
+    // What the display shows, except for the chunks in `unsent`.
+    sent: Framebuffer,
+    unsent: Chunks,
+    history: HistoryBuf<u16, WIDTH>,
+    next_sample_ms: u64,
+    next_draw_ms: u64,
+    next_send_ms: u64,
+}
+
+impl Default for OledScreen {
+    fn default() -> Self {
+        let frame = Framebuffer::new(OLED_HEIGHT);
+        Self {
+            // Whatever the display shows at power on needs replacing.
+            unsent: frame.all_chunks(),
+            sent: frame.clone(),
+            frame,
+            history: HistoryBuf::new_with(0),
+            next_sample_ms: 0,
+            next_draw_ms: 0,
+            next_send_ms: 0,
+        }
+    }
+}
+
+impl OledScreen {
+    /// Bring the display a step closer to up to date.  Called every scan.
+    pub fn update<I: embedded_hal::i2c::I2c>(
+        &mut self,
+        now_ms: u64,
+        status: &Status,
+        deflection: Option<&Point2D<i16>>,
+        display: &mut Ssd1306<I>,
+    ) {
+        if now_ms >= self.next_sample_ms {
+            self.history.write(status.words_per_minute);
+            self.next_sample_ms = now_ms + SAMPLE_INTERVAL_MS;
+        }
+        if now_ms >= self.next_draw_ms {
+            self.next_draw_ms = now_ms + DRAW_INTERVAL_MS;
+            render(&mut self.frame, status, deflection, self.history.oldest_ordered());
+            self.unsent |= self.frame.changed_chunks(&self.sent);
+        }
+
+        if self.unsent == 0 || now_ms < self.next_send_ms {
+            return;
+        }
+        let chunk = usize::try_from(self.unsent.trailing_zeros()).unwrap();
+        match display.send_chunk(&self.frame, chunk) {
+            Ok(()) => {
+                self.sent.copy_chunk(&self.frame, chunk);
+                self.unsent &= !(1 << chunk);
+            }
+            Err(_) => {
+                // It may have been unplugged, and have forgotten everything.
+                self.unsent = self.frame.all_chunks();
+                self.next_send_ms = now_ms + RETRY_INTERVAL_MS;
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// The devices on the I2C bus, found by scanning it at boot.
+//
+// Each kind of device is looked for at the addresses it can have, checked
+// with a handshake where it has something to answer with, and handed to its
+// driver.  Kinds with a handshake are looked for first, as the addresses
+// overlap.  LCD backpacks and port expanders are both PCF8574s, they can
+// only be told apart by address.
+//
+// The gadget board is the joystick, so it is always there to be plugged
+// in, at its usual address if it wasn't found.  So are the LCD backpack and
+// the OLED when built with the `lcd-backpack` and `oled` features.
+
+use defmt::info;
+use embedded_hal::i2c::I2c;
+use rp_pico::hal::Timer;
+
+use pico_play_core::mouse::Point2D;
+use pico_play_core::status_screen::Status;
+use pico_play_core::text_display::TextDisplay;
+
+use crate::accessories::{Accessories, AccessoryInput};
+use crate::gadget::{Gadget, GADGET_ADDRESS};
+use crate::gadget_board::GadgetBoard;
+use crate::hd44780::{Hd44780, BACKPACK_ADDRESSES};
+use crate::i2c_bus::{BusDevice, SharedBus, FAST_MODE};
+use crate::oled_screen::{OledScreen, OLED_HEIGHT};
+use crate::pointing::{Poll, PointingSource};
+use crate::port_expander::{PortExpander, EXPANDER_ADDRESSES};
+use crate::rotary_encoder::{self, RotaryEncoder, ENCODER_ADDRESSES};
+use crate::ssd1306::{Ssd1306, OLED_ADDRESSES};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub(crate) enum PeripheralKind {
+    GadgetBoard,
+    RotaryEncoder,
+    LcdBackpack,
+    Oled,
+    PortExpander,
+}
+
+/// In the order they're looked for.
+const KNOWN: [(PeripheralKind, &[u8]); 5] = [
+    (PeripheralKind::GadgetBoard, &[GADGET_ADDRESS]),
+    (PeripheralKind::RotaryEncoder, &ENCODER_ADDRESSES),
+    (PeripheralKind::LcdBackpack, &BACKPACK_ADDRESSES),
+    (PeripheralKind::Oled, &OLED_ADDRESSES),
+    (PeripheralKind::PortExpander, &EXPANDER_ADDRESSES),
+];
+
+/// Kinds taken to be at their usual address, the first they can have, when
+/// they weren't found.
+const EXPECTED: [(PeripheralKind, bool); 3] = [
+    (PeripheralKind::GadgetBoard, true),
+    (PeripheralKind::LcdBackpack, cfg!(feature = "lcd-backpack")),
+    (PeripheralKind::Oled, cfg!(feature = "oled")),
+];
+
+fn probe(kind: PeripheralKind, i2c: &mut BusDevice, timer: &mut Timer, address: u8) -> bool {
+    match kind {
+        PeripheralKind::GadgetBoard => match Gadget::new(address).query_version(i2c) {
+            Some(version) => {
+                info!("Gadget protocol version {}", version);
+                true
+            }
+            None => false,
+        },
+        PeripheralKind::RotaryEncoder => rotary_encoder::probe(i2c, timer, address),
+        // Nothing to ask, anything which answers will do.
+        PeripheralKind::LcdBackpack | PeripheralKind::Oled | PeripheralKind::PortExpander => {
+            i2c.read(address, &mut [0]).is_ok()
+        }
+    }
+}
+
+pub(crate) struct I2cPeripherals {
+    gadget: GadgetBoard,
+    lcd_backpack: Option<Hd44780<BusDevice<'static>, Timer>>,
+    oled: Option<(Ssd1306<BusDevice<'static>>, OledScreen)>,
+    port_expander: Option<PortExpander<BusDevice<'static>>>,
+    rotary_encoder: Option<RotaryEncoder<BusDevice<'static>, Timer>>,
+}
+
+impl I2cPeripherals {
+    /// Looks for each known kind of device, taking the first of each found.
+    pub fn scan(bus: &'static SharedBus, mut timer: Timer) -> Self {
+        let mut found: heapless::Vec<(PeripheralKind, u8), { KNOWN.len() }> = heapless::Vec::new();
+        let taken = |found: &[(PeripheralKind, u8)], address: &u8| found.iter().any(|(_, a)| a == address);
+        for (kind, addresses) in KNOWN {
+            let address = addresses.iter()
+                .filter(|a| !taken(&found, a))
+                .find(|a| probe(kind, &mut bus.device(), &mut timer, **a));
+            if let Some(address) = address {
+                info!("Found {} at {:#x}", kind, address);
+                found.push((kind, *address)).unwrap();
+            }
+        }
+        for (kind, addresses) in KNOWN {
+            let expected = EXPECTED.iter().any(|(k, expected)| *k == kind && *expected);
+            let address = addresses[0];
+            if expected && !found.iter().any(|(k, _)| *k == kind) && !taken(&found, &address) {
+                info!("Expecting {} at {:#x}", kind, address);
+                found.push((kind, address)).unwrap();
+            }
+        }
+        let address_of = |kind| found.iter().find(|(k, _)| *k == kind).map(|(_, a)| *a);
+
+        // Sending the OLED its pictures is much quicker in fast mode, which
+        // the PCF8574s can't manage.
+        let slow = [PeripheralKind::LcdBackpack, PeripheralKind::PortExpander];
+        if address_of(PeripheralKind::Oled).is_some() && !slow.iter().any(|k| address_of(*k).is_some()) {
+            info!("I2C fast mode");
+            bus.set_frequency(FAST_MODE);
+        }
+
+        Self {
+            gadget: GadgetBoard::new(bus, address_of(PeripheralKind::GadgetBoard).unwrap_or(GADGET_ADDRESS)),
+            lcd_backpack: address_of(PeripheralKind::LcdBackpack)
+                .map(|a| Hd44780::new(bus.device(), timer, a)),
+            oled: address_of(PeripheralKind::Oled)
+                .map(|a| (Ssd1306::new(bus.device(), a, OLED_HEIGHT), OledScreen::default())),
+            port_expander: address_of(PeripheralKind::PortExpander)
+                .map(|a| PortExpander::new(bus.device(), a)),
+            rotary_encoder: address_of(PeripheralKind::RotaryEncoder)
+                .map(|a| RotaryEncoder::new(bus.device(), timer, a)),
+        }
+    }
+}
+
+impl PointingSource for I2cPeripherals {
+    fn poll(&mut self, now_ms: u64) -> Poll {
+        self.gadget.poll(now_ms)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.gadget.is_connected()
+    }
+}
+
+impl Accessories for I2cPeripherals {
+    /// The backpack if there is one, otherwise the LCD on the gadget board.
+    fn text_display(&mut self) -> &mut dyn TextDisplay {
+        match &mut self.lcd_backpack {
+            Some(lcd) => lcd,
+            None => &mut self.gadget,
+        }
+    }
+
+    fn update(&mut self, now_ms: u64, status: &Status, deflection: Option<&Point2D<i16>>) -> AccessoryInput {
+        if let Some(lcd) = &mut self.lcd_backpack {
+            lcd.poll(now_ms);
+        }
+        if let Some((oled, screen)) = &mut self.oled {
+            screen.update(now_ms, status, deflection, oled);
+        }
+        if let Some(expander) = &mut self.port_expander {
+            expander.update(status);
+        }
+        AccessoryInput {
+            wheel_detents: self.rotary_encoder.as_mut().map_or(0, |encoder| encoder.poll(now_ms)),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// Where joystick readings come from.  The board is built with one source,
+// chosen by Cargo feature: the Arduino gadget board over I2C, or the
+// RP2040's own ADC.
+
+use pico_play_core::mouse::ButtonState;
+use pico_play_core::mouse::Point2D;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+// A joystick on the ADC is always there.
+#[cfg_attr(feature = "adc-joystick", allow(dead_code))]
+pub(crate) enum LinkEvent {
+    Connected,
+    Disconnected,
+}
+
+/// The result of polling a source.
+#[derive(Default)]
+pub(crate) struct Poll {
+    /// Raw position of the stick, in the source's own counts, and its
+    /// button.  None if there's no fresh reading.
+    pub reading: Option<(Point2D<i16>, ButtonState)>,
+    /// The stick appeared or went away.
+    pub event: Option<LinkEvent>,
+}
+
+pub(crate) trait PointingSource {
+    fn poll(&mut self, now_ms: u64) -> Poll;
+
+    /// Whether the stick is there to read.
+    fn is_connected(&self) -> bool;
+}
@@ -0,0 +1,43 @@
+// LEDs on a PCF8574 port expander, for Caps Lock, mouse mode and the Fn
+// layer.  The LEDs are wired from the supply to the pins, so a pin driven
+// low lights one.  The other pins are left high, as inputs.
+
+use embedded_hal::i2c::I2c;
+
+use pico_play_core::status_screen::Status;
+
+/// A PCF8574 with A0..A2 set anywhere but all high, which is the LCD
+/// backpacks' address.
+pub(crate) const EXPANDER_ADDRESSES: [u8; 7] = [0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26];
+
+const CAPS_LOCK_LED: u8 = 0x01;
+const MOUSE_MODE_LED: u8 = 0x02;
+const FN_LAYER_LED: u8 = 0x04;
+
+pub(crate) struct PortExpander<I> {
+    i2c: I,
+    address: u8,
+    // LEDs last lit, None if that isn't known.
+    lit: Option<u8>,
+}
+
+impl<I: I2c> PortExpander<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address, lit: None }
+    }
+
+    pub fn update(&mut self, status: &Status) {
+        let lit = [
+            (status.caps_lock, CAPS_LOCK_LED),
+            (status.mouse_mode, MOUSE_MODE_LED),
+            (status.fn_layer, FN_LAYER_LED),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .fold(0, |lit, (_, led)| lit | led);
+        if self.lit == Some(lit) {
+            return;
+        }
+        self.lit = self.i2c.write(self.address, &[!lit]).ok().map(|_| lit);
+    }
+}
@@ -0,0 +1,144 @@
+// Keyboard HID device which is either NKRO or a strict 6KRO boot keyboard.
+//
+// Based on NKROBootKeyboard from usbd-human-interface-device, but the
+// report descriptor is chosen when the device is built, and only the 8 byte
+// boot report is sent when the host asks for the boot protocol.
+
+use fugit::{ExtU32, MillisDurationU32};
+use packed_struct::PackedStruct;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usb_device::UsbError;
+use usbd_human_interface_device::device::keyboard::KeyboardLedsReport;
+use usbd_human_interface_device::device::keyboard::NKROBootKeyboardReport;
+use usbd_human_interface_device::device::keyboard::BOOT_KEYBOARD_REPORT_DESCRIPTOR;
+use usbd_human_interface_device::device::keyboard::NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR;
+use usbd_human_interface_device::page::Keyboard;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+use pico_play_core::settings::RolloverMode;
+
+/// Length of the boot keyboard report, which is also the start of the NKRO report.
+const BOOT_REPORT_LEN: usize = 8;
+
+pub(crate) struct RolloverKeyboard<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes32, OutBytes8, ReportSingle>,
+    mode: RolloverMode,
+    last_report: Option<NKROBootKeyboardReport>,
+    since_last_report: MillisDurationU32,
+    // Lock lights as last set by the host.
+    leds: KeyboardLedsReport,
+}
+
+impl<B: UsbBus> RolloverKeyboard<'_, B> {
+    /// In 6KRO mode more than six keys held reports ErrorRollOver in every slot.
+    pub fn write_report<K: IntoIterator<Item = Keyboard>>(&mut self, keys: K) -> Result<(), UsbHidError> {
+        let report = NKROBootKeyboardReport::new(keys);
+        if self.last_report == Some(report) {
+            return Err(UsbHidError::Duplicate);
+        }
+
+        self.send(&report)?;
+        self.last_report = Some(report);
+        self.since_last_report = 0.millis();
+        Ok(())
+    }
+
+    pub fn read_report(&mut self) -> usb_device::Result<KeyboardLedsReport> {
+        let data = &mut [0];
+        self.interface.read_report(data)?;
+        self.leds = KeyboardLedsReport::unpack(data).map_err(|_| UsbError::ParseError)?;
+        Ok(self.leds)
+    }
+
+    pub fn leds(&self) -> KeyboardLedsReport {
+        self.leds
+    }
+
+    fn boot_report_only(&self) -> bool {
+        self.mode == RolloverMode::Boot6kro || self.interface.protocol() == HidProtocol::Boot
+    }
+
+    fn send(&mut self, report: &NKROBootKeyboardReport) -> Result<(), UsbHidError> {
+        let data = report.pack().map_err(|_| UsbHidError::SerializationError)?;
+        let len = if self.boot_report_only() { BOOT_REPORT_LEN } else { data.len() };
+        self.interface
+            .write_report(&data[..len])
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for RolloverKeyboard<'a, B> {
+    type I = Interface<'a, B, InBytes32, OutBytes8, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {
+        self.last_report = None;
+        self.since_last_report = 0.millis();
+        self.leds = KeyboardLedsReport::default();
+    }
+
+    // Repeat the last report when the host has asked for an idle rate.
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        let idle = self.interface.global_idle();
+        if idle.ticks() == 0 {
+            return Ok(());
+        }
+
+        if self.since_last_report < idle {
+            self.since_last_report += 1.millis();
+            return Ok(());
+        }
+
+        match self.last_report {
+            Some(report) => {
+                self.send(&report)?;
+                self.since_last_report = 0.millis();
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+pub(crate) struct RolloverKeyboardConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes32, OutBytes8, ReportSingle>,
+    mode: RolloverMode,
+}
+
+impl RolloverKeyboardConfig<'_> {
+    pub fn new(mode: RolloverMode, poll_interval: MillisDurationU32) -> Self {
+        let (descriptor, description) = match mode {
+            RolloverMode::Nkro => (NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR, "NKRO Keyboard"),
+            RolloverMode::Boot6kro => (BOOT_KEYBOARD_REPORT_DESCRIPTOR, "Keyboard"),
+        };
+
+        Self {
+            interface: InterfaceBuilder::with_static_descriptor(descriptor).unwrap()
+                .description(description)
+                .boot_device(InterfaceProtocol::Keyboard)
+                .idle_default(500.millis()).unwrap()
+                .in_endpoint(poll_interval).unwrap()
+                .with_out_endpoint(100.millis()).unwrap()
+                .build(),
+            mode,
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for RolloverKeyboardConfig<'a> {
+    type Allocated = RolloverKeyboard<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        RolloverKeyboard {
+            interface: Interface::new(usb_alloc, self.interface),
+            mode: self.mode,
+            last_report: None,
+            since_last_report: 0.millis(),
+            leds: KeyboardLedsReport::default(),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+// Adafruit's I2C rotary encoder board, an ATtiny running their seesaw
+// firmware.  Turning it scrolls.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+/// 0x36 with no address jumpers bridged, up to 0x3d with all three.
+pub(crate) const ENCODER_ADDRESSES: [u8; 8] = [0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d];
+
+// Seesaw registers, a module and a function within it.
+const STATUS_BASE: u8 = 0x00;
+const STATUS_HW_ID: u8 = 0x01;
+const ENCODER_BASE: u8 = 0x11;
+const ENCODER_DELTA: u8 = 0x40;
+/// Hardware IDs of the chips seesaw runs on, SAMD09 and the ATtinys.
+const SEESAW_HW_IDS: [u8; 7] = [0x55, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89];
+/// The ATtiny needs a moment between being told a register and reading it.
+const REGISTER_DELAY_US: u32 = 250;
+const POLL_INTERVAL_MS: u64 = 10;
+/// More detents than a hand turns between polls, a bigger count is garbage.
+const MAX_DETENTS_PER_POLL: i32 = 20;
+
+fn read_register<I: I2c>(
+    i2c: &mut I,
+    delay: &mut impl DelayNs,
+    address: u8,
+    register: [u8; 2],
+    buffer: &mut [u8],
+) -> Result<(), I::Error> {
+    i2c.write(address, &register)?;
+    delay.delay_us(REGISTER_DELAY_US);
+    i2c.read(address, buffer)
+}
+
+/// Whether there is a seesaw board at `address`.
+pub(crate) fn probe<I: I2c>(i2c: &mut I, delay: &mut impl DelayNs, address: u8) -> bool {
+    let mut id = [0u8];
+    read_register(i2c, delay, address, [STATUS_BASE, STATUS_HW_ID], &mut id).is_ok()
+        && SEESAW_HW_IDS.contains(&id[0])
+}
+
+pub(crate) struct RotaryEncoder<I, D> {
+    i2c: I,
+    delay: D,
+    address: u8,
+    next_poll_ms: u64,
+}
+
+impl<I: I2c, D: DelayNs> RotaryEncoder<I, D> {
+    pub fn new(i2c: I, delay: D, address: u8) -> Self {
+        Self { i2c, delay, address, next_poll_ms: 0 }
+    }
+
+    /// Detents turned since last time, clockwise positive.  Reading resets
+    /// the board's count.
+    pub fn poll(&mut self, now_ms: u64) -> i32 {
+        if now_ms < self.next_poll_ms {
+            return 0;
+        }
+        self.next_poll_ms = now_ms + POLL_INTERVAL_MS;
+
+        let mut delta = [0u8; 4];
+        match read_register(&mut self.i2c, &mut self.delay, self.address, [ENCODER_BASE, ENCODER_DELTA], &mut delta) {
+            // Seesaw counts anticlockwise up.
+            Ok(()) => i32::from_be_bytes(delta)
+                .saturating_neg()
+                .clamp(-MAX_DETENTS_PER_POLL, MAX_DETENTS_PER_POLL),
+            Err(_) => 0,
+        }
+    }
+}
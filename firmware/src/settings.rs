@@ -0,0 +1,16 @@
+// Settings kept in a page of flash, laid out as `Settings` says.
+
+use pico_play_core::settings::Settings;
+
+use crate::flash;
+
+/// Load settings from flash, falling back to defaults if none have been saved.
+pub(crate) fn load() -> Settings {
+    Settings::read_from(flash::read_settings_page()).unwrap_or_default()
+}
+
+pub(crate) fn save(settings: &Settings) {
+    let mut page = [0xffu8; flash::PAGE_SIZE];
+    settings.write_to(&mut page);
+    flash::write_settings_page(&page);
+}
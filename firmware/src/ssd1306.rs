@@ -0,0 +1,95 @@
+// SSD1306 OLED controller over I2C, for 128x32 and 128x64 displays.
+//
+// Frames go over a chunk of a page at a time, see `Framebuffer`.  Like the
+// LCD the display is set up on first use, and again after anything fails.
+
+use embedded_hal::i2c::{Error, ErrorKind, I2c};
+
+use pico_play_core::framebuffer::{Framebuffer, CHUNK_WIDTH};
+use pico_play_core::text_display::DisplayError;
+
+/// With SA0 low, the usual wiring, and high.
+pub(crate) const OLED_ADDRESSES: [u8; 2] = [0x3c, 0x3d];
+
+/// Control bytes, what follows is commands or display data.
+const COMMANDS: u8 = 0x00;
+const DATA: u8 = 0x40;
+
+const SET_COLUMN_RANGE: u8 = 0x21;
+const SET_PAGE_RANGE: u8 = 0x22;
+
+fn classify(e: impl Error) -> DisplayError {
+    match e.kind() {
+        ErrorKind::NoAcknowledge(_) => DisplayError::Absent,
+        _ => DisplayError::Bus,
+    }
+}
+
+pub(crate) struct Ssd1306<I> {
+    i2c: I,
+    address: u8,
+    height: usize,
+    ready: bool,
+}
+
+impl<I: I2c> Ssd1306<I> {
+    pub fn new(i2c: I, address: u8, height: usize) -> Self {
+        Self { i2c, address, height, ready: false }
+    }
+
+    fn commands(&mut self, commands: &[u8]) -> Result<(), DisplayError> {
+        let mut bytes = [0u8; 32];
+        bytes[0] = COMMANDS;
+        bytes[1..=commands.len()].copy_from_slice(commands);
+        self.i2c.write(self.address, &bytes[..=commands.len()]).map_err(classify)
+    }
+
+    fn init(&mut self) -> Result<(), DisplayError> {
+        let multiplex = u8::try_from(self.height - 1).unwrap();
+        // How the COM pins are wired to the glass differs with the height.
+        let com_pins = if self.height == 64 { 0x12 } else { 0x02 };
+        #[rustfmt::skip]
+        let setup = [
+            0xae,             // Display off
+            0xd5, 0x80,       // Clock divide and oscillator
+            0xa8, multiplex,  // Rows
+            0xd3, 0x00,       // No vertical offset
+            0x40,             // Start at RAM row 0
+            0x8d, 0x14,       // Charge pump on
+            0x20, 0x00,       // Horizontal addressing
+            0xa1,             // Column 127 on the left, as the modules are mounted
+            0xc8,             // Scan rows bottom up, likewise
+            0xda, com_pins,
+            0x81, 0x8f,       // Contrast
+            0xd9, 0xf1,       // Precharge
+            0xdb, 0x40,       // VCOMH level
+            0xa4,             // Show RAM
+            0xa6,             // Not inverted
+            0xaf,             // Display on
+        ];
+        self.commands(&setup)
+    }
+
+    /// Sends one chunk of `frame`.  A chunk is 16 columns, under 3ms on
+    /// the bus at 100kHz.
+    pub fn send_chunk(&mut self, frame: &Framebuffer, chunk: usize) -> Result<(), DisplayError> {
+        if !self.ready {
+            self.init()?;
+            self.ready = true;
+        }
+
+        let (page, columns) = Framebuffer::chunk_location(chunk);
+        let page = u8::try_from(page).unwrap();
+        let (first, last) = (u8::try_from(columns.start).unwrap(), u8::try_from(columns.end - 1).unwrap());
+        let result = self.commands(&[SET_COLUMN_RANGE, first, last, SET_PAGE_RANGE, page, page]).and_then(|_| {
+            let mut bytes = [0u8; CHUNK_WIDTH + 1];
+            bytes[0] = DATA;
+            bytes[1..].copy_from_slice(frame.chunk(chunk));
+            self.i2c.write(self.address, &bytes).map_err(classify)
+        });
+        if result.is_err() {
+            self.ready = false;
+        }
+        result
+    }
+}
@@ -0,0 +1,52 @@
+use usb_device::device::UsbDeviceState;
+
+/// Tracks USB suspend / resume, and whether we have asked the host to wake.
+///
+/// Keys pressed during suspend are kept in the `KeyReportQueue`, which is
+/// not drained while suspended, so the press which woke the host is still
+/// reported after resume.
+#[derive(Default)]
+pub(crate) struct SuspendTracker {
+    suspended: bool,
+    wakeup_signalled: bool,
+}
+
+/// What changed since the previous call to `SuspendTracker::update_state`.
+#[derive(PartialEq, Eq, defmt::Format)]
+pub(crate) enum PowerTransition {
+    None,
+    Suspended,
+    Resumed,
+}
+
+impl SuspendTracker {
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    pub fn update_state(&mut self, state: UsbDeviceState) -> PowerTransition {
+        let suspended = state == UsbDeviceState::Suspend;
+        match (self.suspended, suspended) {
+            (false, true) => {
+                self.suspended = true;
+                self.wakeup_signalled = false;
+                PowerTransition::Suspended
+            }
+            (true, false) => {
+                self.suspended = false;
+                PowerTransition::Resumed
+            }
+            _ => PowerTransition::None,
+        }
+    }
+
+    /// Returns true if a key press should ask the host to wake up.
+    pub fn should_wake(&mut self, key_pressed: bool, remote_wakeup_enabled: bool) -> bool {
+        if self.suspended && key_pressed && remote_wakeup_enabled && !self.wakeup_signalled {
+            self.wakeup_signalled = true;
+            true
+        } else {
+            false
+        }
+    }
+}
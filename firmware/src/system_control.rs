@@ -0,0 +1,88 @@
+// HID System Control device, for power, sleep and wake keys.
+//
+// Sends a single usage from the Generic Desktop page, or zero for no key.
+
+use fugit::ExtU32;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usbd_human_interface_device::usb_class::prelude::*;
+
+use pico_play_core::key_table::SystemKey;
+
+#[rustfmt::skip]
+pub(crate) const SYSTEM_CONTROL_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop),
+    0x09, 0x80,       // Usage (System Control),
+    0xA1, 0x01,       // Collection (Application),
+    0x19, 0x81,       //   Usage Minimum (System Power Down),
+    0x29, 0x83,       //   Usage Maximum (System Wake Up),
+    0x16, 0x81, 0x00, //   Logical Minimum (0x81),
+    0x26, 0x83, 0x00, //   Logical Maximum (0x83),
+    0x75, 0x08,       //   Report Size (8),
+    0x95, 0x01,       //   Report Count (1),
+    0x81, 0x00,       //   Input (Data, Array, Absolute),
+    0xC0,             // End Collection
+];
+
+pub(crate) struct SystemControl<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutNone, ReportSingle>,
+    last_report: Option<u8>,
+}
+
+impl<B: UsbBus> SystemControl<'_, B> {
+    /// `None` when no system key is held.
+    pub fn write_report(&mut self, key: Option<SystemKey>) -> Result<(), UsbHidError> {
+        let report = key.map_or(0, |k| u8::from(k.usage()));
+        if self.last_report == Some(report) {
+            return Err(UsbHidError::Duplicate);
+        }
+
+        self.interface
+            .write_report(&[report])
+            .map_err(UsbHidError::from)?;
+        self.last_report = Some(report);
+        Ok(())
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for SystemControl<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutNone, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {
+        self.last_report = None;
+    }
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
+
+pub(crate) struct SystemControlConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutNone, ReportSingle>,
+}
+
+impl Default for SystemControlConfig<'_> {
+    fn default() -> Self {
+        Self {
+            interface: InterfaceBuilder::with_static_descriptor(SYSTEM_CONTROL_REPORT_DESCRIPTOR).unwrap()
+                .description("System Control")
+                .in_endpoint(50.millis()).unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for SystemControlConfig<'a> {
+    type Allocated = SystemControl<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        SystemControl {
+            interface: Interface::new(usb_alloc, self.interface),
+            last_report: None,
+        }
+    }
+}